@@ -1,4 +1,4 @@
-import init, { generate, get_cell_index, mark_errors } from "../pkg/infinite_sudoku.js";
+import init, { generate, get_cell_index, mark_errors, Renderer } from "../pkg/infinite_sudoku.js";
 import glSetup from "./webgl.js";
 
 const canvas = document.getElementsByTagName("canvas")[0];
@@ -12,8 +12,10 @@ const u_window_resolution = gl.uniform("u_window_resolution", "2fv", [
 
 const u_mouse_coords = gl.uniform("u_mouse_coords", "2fv", [0, 0]);
 const u_selected_cell = gl.uniform("u_selected_cell", "2fv", [Infinity, Infinity]);
-const u_selected_value = gl.uniform("u_selected_value", "1i", 0);
-u_selected_cell.onchange(updateSelectedValue);
+u_selected_cell.onchange(() => {
+    updateSelectedValue();
+    updateSudokuData(); // the highlights are part of the render buffer
+});
 
 const u_number_texture = gl.texture("u_numbers_texture");
 u_number_texture.loadImage("./assets/numbers1024.png", 0).then(() => u_number_texture.activateMipmap());
@@ -25,8 +27,25 @@ let [n, m] = [1, 1];
 const u_world_size = gl.uniform("u_world_size", "2fv", [n, m]);
 
 let data = generate(1, 1, false);
+const renderer = new Renderer();
+let uploaded_sudokus = 0;
 function updateSudokuData() {
-    u_sudoku.setSourceArray(data, 7 * 9, n * m, gl.internal.LUMINANCE);
+    // one RGBA texel per cell with its value, flags and highlights (see `render.rs`),
+    // and one texture row per sudoku
+    const [bx, by] = u_selected_cell.get();
+    const selected = getCellIndexFromCoords(bx, by) ?? undefined;
+    renderer.update(data, n, m, undefined, selected);
+    const ranges = renderer.take_dirty_ranges();
+    if (uploaded_sudokus != n * m) {
+        // the world was resized, so the texture needs a new size
+        uploaded_sudokus = n * m;
+        u_sudoku.setSourceArray(renderer.buffer(), 7 * 9, n * m, gl.internal.RGBA);
+        return;
+    }
+    for (let i = 0; i < ranges.length; i += 2) {
+        const [start, end] = [ranges[i], ranges[i + 1]];
+        u_sudoku.setSubArray(renderer.rows(start, end), 0, start, 7 * 9, end - start, gl.internal.RGBA);
+    }
 }
 updateSudokuData();
 
@@ -127,13 +146,12 @@ function getCellIndexFromCoords(x, y) {
 }
 
 /**
- * Set the u_selected_value to match the value of u_selected_cell
+ * Enable the numpad if the value of u_selected_cell can be changed
  */
 function updateSelectedValue() {
     const [bx, by] = u_selected_cell.get();
     const i = getCellIndexFromCoords(bx, by);
     if (i === null) {
-        u_selected_value.set(0);
         numpad_container.classList.add("disabled");
         return;
     }
@@ -143,7 +161,6 @@ function updateSelectedValue() {
     } else {
         numpad_container.classList.remove("disabled");
     }
-    u_selected_value.set(data[i] & 15);
 }
 
 /**
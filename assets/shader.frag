@@ -7,7 +7,6 @@ out vec4 frag_color;
 
 uniform vec2 u_mouse_coords;
 uniform vec2 u_selected_cell;
-uniform int u_selected_value;
 uniform sampler2D u_numbers_texture;
 uniform sampler2D u_sudoku;
 uniform float u_inv_scale;
@@ -16,6 +15,12 @@ uniform float u_finished_animation;
 
 const float THIRD = 1.0 / 3.0;
 
+// flags in the blue channel of the render buffer (see `render.rs`)
+const int SELECTED = 2;
+const int PEER = 4;
+const int SAME_VALUE = 8;
+const int SELECTED_SUDOKU = 32;
+
 // TODO: add dark mode?
 const vec3 col_num = vec3(0.0);
 const vec3 col_selected_num = vec3(0.0, 0.22, 0.58);
@@ -67,6 +72,13 @@ void main() {
 	float block_index = max(fsudoku_uv.x + fsudoku_uv.y * 3.0 - 2.0, 0.0);
 	float index = 9.0 * block_index + cell_index;
 
+	// every row of the render buffer holds the cells of one sudoku
+	vec2 world_sudoku = rounded_mod(sudoku_coord, u_world_size);
+	int sudoku_index = int(world_sudoku.x + world_sudoku.y * u_world_size.x);
+	ivec4 texel = ivec4(255.0 * texelFetch(u_sudoku, ivec2(int(index), sudoku_index), 0) + 0.5);
+	int cell_data = texel.r;
+	int flags = texel.b;
+
 	// finish animation
 	vec2 cell_coord = floor(v_uv);
 	vec2 selected_cell = floor(u_selected_cell);
@@ -76,41 +88,15 @@ void main() {
 	}
 
 	// highlight selected sudoku(s)
-	vec2 selected_fb4x4 = floor(mod(u_selected_cell, 12.0) / 3.0);
-	vec2 selected_sudoku = floor(u_selected_cell / 12.0);
-	selected_sudoku = vec2(selected_sudoku.x - selected_sudoku.y, -selected_sudoku.x - selected_sudoku.y);
-	selected_sudoku += vec2(0.0, 1.0) * float(selected_fb4x4.x == 0.0 && selected_fb4x4.y < 2.0);
-	selected_sudoku += vec2(1.0, 0.0) * float(selected_fb4x4.x >= 2.0 && selected_fb4x4.y < 2.0);
-	selected_sudoku += vec2(0.0, -1.0) * float(selected_fb4x4.x == 3.0 && selected_fb4x4.y == 3.0);
-	vec2 selected_sudoku2 = selected_sudoku;
-	selected_sudoku += vec2(0.0, -1.0) * float(
-		(selected_fb4x4.x == 0.0 && selected_fb4x4.y == 1.0) || (selected_fb4x4.x == 2.0 && selected_fb4x4.y == 3.0)
-	);
-	selected_sudoku += vec2(-1.0, 0.0) * float(
-		(selected_fb4x4.x == 0.0 && selected_fb4x4.y == 3.0) || (selected_fb4x4.x == 2.0 && selected_fb4x4.y == 1.0)
-	);
-	vec2 sudoku_coord2 = sudoku_coord;
-	sudoku_coord2 += vec2(0.0, -1.0) * float((fb4x4.x == 0.0 && fb4x4.y == 1.0) || (fb4x4.x == 2.0 && fb4x4.y == 3.0));
-	sudoku_coord2 += vec2(-1.0, 0.0) * float((fb4x4.x == 0.0 && fb4x4.y == 3.0) || (fb4x4.x == 2.0 && fb4x4.y == 1.0));
-
-	bool is_hovered_sudoku = selected_sudoku == sudoku_coord || selected_sudoku2 == sudoku_coord || selected_sudoku == sudoku_coord2 || selected_sudoku2 == sudoku_coord2;
-	is_hovered_sudoku = is_hovered_sudoku && !((selected_fb4x4.x == 1.0 && selected_fb4x4.y == 0.0) || (selected_fb4x4.x == 3.0 && selected_fb4x4.y == 2.0));
-	color = mix(color, col_selected_cell, 0.3 * float(is_hovered_sudoku));
+	color = mix(color, col_selected_cell, 0.3 * float((flags & SELECTED_SUDOKU) > 0));
 
 	// highlight selected row/column/block
-	vec2 block_coord = floor(v_uv / 3.0);
-	vec2 selected_block = floor(u_selected_cell / 3.0);
-	color = mix(color, col_selected_cell, 0.4 * float(is_hovered_sudoku &&
-		(selected_cell.x == cell_coord.x || selected_cell.y == cell_coord.y || block_coord == selected_block)));
+	color = mix(color, col_selected_cell, 0.4 * float((flags & (PEER | SELECTED)) > 0));
 
 	// highlight selected cell
-	color = mix(color, col_selected_cell, float(selected_cell == cell_coord));
+	color = mix(color, col_selected_cell, float((flags & SELECTED) > 0));
 
 	// add number in cell
-	sudoku_coord = rounded_mod(sudoku_coord, u_world_size);
-	float sudoku_index = sudoku_coord.x + sudoku_coord.y * u_world_size.x;
-	sudoku_index /= u_world_size.x * u_world_size.y - 1.0;
-	int cell_data = int(255.0 * texture(u_sudoku, vec2(index / (7.0 * 9.0 - 1.0), sudoku_index)));
 	bool user_entered = (cell_data & 16) > 0;
 	bool number_error = (cell_data & 32) > 0;
 	color = mix(color, col_error, 0.6 * float(number_error));
@@ -127,7 +113,7 @@ void main() {
 
 	float num_text = mix(num_bold, num_normal, float(user_entered));
 
-	vec3 num_color = mix(col_num, col_selected_num, float(number == u_selected_value));
+	vec3 num_color = mix(col_num, col_selected_num, float((flags & SAME_VALUE) > 0));
 
 	color = mix(color, num_color, num_text * float(number > 0 && number <= 9));
 
@@ -198,6 +198,20 @@ export default async function glSetup(canvas) {
                     gl.activeTexture(gl.TEXTURE0 + index);
                     gl.texImage2D(gl.TEXTURE_2D, 0, format, width, height, 0, format, gl.UNSIGNED_BYTE, data);
                 },
+                /**
+                 * Replace a rectangle of the texture, which must already have a size
+                 * @param {Uint8Array} data
+                 * @param {number} x
+                 * @param {number} y
+                 * @param {number} width
+                 * @param {number} height
+                 * @param {keyof channelsPerFormat} format
+                 */
+                setSubArray(data, x, y, width, height, format = gl.RGBA) {
+                    gl.pixelStorei(gl.UNPACK_ALIGNMENT, 1);
+                    gl.activeTexture(gl.TEXTURE0 + index);
+                    gl.texSubImage2D(gl.TEXTURE_2D, 0, x, y, width, height, format, gl.UNSIGNED_BYTE, data);
+                },
                 /**
                  * Load an image to use as source for the texture
                  * @param {string} src
@@ -0,0 +1,466 @@
+//! Cages of killer sudokus
+//!
+//! A cage is a group of cells with a sum clue: its values add up to the sum, and no value
+//! is repeated in it. Cages can go across shared blocks into other sudokus. They are passed
+//! to and from JavaScript as a flat list of numbers (see [`Cage::to_flat`]).
+
+use crate::sudokus::SudokuGrid;
+
+/// Group of cells whose values add up to `sum` without repeating a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cage {
+    pub sum: u32,
+    /// Cell indexes, starting with the cell the sum is written in
+    pub cells: Box<[usize]>,
+}
+
+impl Cage {
+    /// Write cages as a flat list: the sum and the number of cells of every cage, followed
+    /// by its cells
+    pub fn to_flat(cages: &[Cage]) -> Box<[usize]> {
+        let mut flat = Vec::new();
+        for cage in cages {
+            flat.push(cage.sum as usize);
+            flat.push(cage.cells.len());
+            flat.extend(cage.cells.iter());
+        }
+        flat.into()
+    }
+
+    /// Read cages from a list created by [`Cage::to_flat`]
+    pub fn from_flat(mut flat: &[usize]) -> Result<Vec<Cage>, CageError> {
+        let mut cages = Vec::new();
+        while !flat.is_empty() {
+            let [sum, len, rest @ ..] = flat else {
+                return Err(CageError::Truncated);
+            };
+            let (cells, rest) = rest.split_at_checked(*len).ok_or(CageError::Truncated)?;
+            cages.push(Cage {
+                sum: u32::try_from(*sum).map_err(|_| CageError::InvalidSum(*sum))?,
+                cells: cells.into(),
+            });
+            flat = rest;
+        }
+        Ok(cages)
+    }
+}
+
+/// Reason cages can not be added to a grid
+#[derive(Debug, PartialEq)]
+pub enum CageError {
+    /// Flat list that ends in the middle of a cage
+    Truncated,
+    /// Sum that does not fit in a cage
+    InvalidSum(usize),
+    /// Cage without cells
+    Empty,
+    /// Cell index that is not in the grid
+    OutOfBounds(usize),
+    /// Cell that is part of several cages
+    Overlapping(usize),
+}
+
+impl std::fmt::Display for CageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CageError::Truncated => write!(f, "cage list ends in the middle of a cage"),
+            CageError::InvalidSum(sum) => write!(f, "invalid cage sum {sum}"),
+            CageError::Empty => write!(f, "cage without cells"),
+            CageError::OutOfBounds(i) => write!(f, "cage cell {i} is not in the grid"),
+            CageError::Overlapping(i) => write!(f, "cell {i} is part of several cages"),
+        }
+    }
+}
+
+impl std::error::Error for CageError {}
+
+/// Check if `count` different values from the bitmap `available` (bit `v` for value `v`)
+/// can add up to `sum`
+pub fn sum_is_possible(available: u32, count: usize, sum: u32) -> bool {
+    if count == 0 {
+        return sum == 0;
+    }
+    let mut rest = available;
+    while rest != 0 {
+        let value = rest.trailing_zeros();
+        rest &= rest - 1;
+        // values are tried from small to large, so the others are larger than this one
+        if value * count as u32 > sum {
+            break;
+        }
+        if sum_is_possible(rest, count - 1, sum - value) {
+            return true;
+        }
+    }
+    false
+}
+
+impl SudokuGrid {
+    /// Check if the value in the cell at index i is repeated in its cage, or if its cage
+    /// adds up to more than its sum (or to anything else once every cell is filled)
+    pub fn cage_is_problematic(&self, i: usize) -> bool {
+        let Some(cage) = self.cage_for(i) else {
+            return false;
+        };
+        let value = self.value(i);
+        let (mut total, mut full, mut seen) = (0, true, false);
+        for c in cage.cells.iter() {
+            let v = self.value(*c);
+            if v == value && *c != i {
+                seen = true;
+            }
+            total += v as u32;
+            full &= v > 0;
+        }
+        seen || total > cage.sum || (full && total != cage.sum)
+    }
+
+    /// Check if `value` in the empty cell at index i still leaves a way to complete its
+    /// cage: different values that are not in the cage yet, adding up to the rest of the
+    /// sum (always true for cells that are not in a cage)
+    pub fn cage_allows(&self, i: usize, value: u8) -> bool {
+        let Some(cage) = self.cage_for(i) else {
+            return true;
+        };
+        let all_values = ((1 << self.boxes().values()) - 1) << 1;
+        let (mut used, mut total, mut empty) = (0, value as u32, 0);
+        for c in cage.cells.iter().filter(|c| **c != i) {
+            match self.value(*c) {
+                0 => empty += 1,
+                v => {
+                    used |= 1 << v;
+                    total += v as u32;
+                }
+            }
+        }
+        if used & (1 << value) != 0 {
+            return false;
+        }
+        used |= 1 << value;
+        total <= cage.sum && sum_is_possible(all_values & !used, empty, cage.sum - total)
+    }
+
+    /// Check if every cage adds up to its sum, without repeating a value
+    pub fn cages_are_solved(&self) -> bool {
+        self.cages().iter().all(|cage| {
+            let mut values = cage
+                .cells
+                .iter()
+                .map(|c| self.value(*c))
+                .collect::<Vec<_>>();
+            let total = values.iter().map(|v| *v as u32).sum::<u32>();
+            values.sort_unstable();
+            values.dedup();
+            values.len() == cage.cells.len() && !values.contains(&0) && total == cage.sum
+        })
+    }
+
+    /// Split the solved world into cages of 2 to `max_size` cells next to each other (in a
+    /// sudoku, so cages can continue through a shared block into the next sudoku), taking
+    /// the sums from the values in the grid
+    ///
+    /// `random(n)` should return a random number below `n`. Cages start from the cells with
+    /// the fewest free neighbours, so few cells are left over. A cell that is left over
+    /// anyway joins a cage next to it (which can then get larger than `max_size`), or stays
+    /// in a cage on its own.
+    pub fn random_cages(
+        &self,
+        max_size: usize,
+        mut random: impl FnMut(usize) -> usize,
+    ) -> Vec<Cage> {
+        let layout = self.layout();
+        let len = self.cells.len();
+        let last = self.boxes().values() - 1;
+        let neighbours = (0..len)
+            .map(|i| {
+                let mut neighbours = Vec::new();
+                for p in layout.positions(i) {
+                    let (sudoku, row, column) = (p.sudoku, p.row, p.column);
+                    if row > 0 {
+                        neighbours.push(layout.index(sudoku, row - 1, column));
+                    }
+                    if row < last {
+                        neighbours.push(layout.index(sudoku, row + 1, column));
+                    }
+                    if column > 0 {
+                        neighbours.push(layout.index(sudoku, row, column - 1));
+                    }
+                    if column < last {
+                        neighbours.push(layout.index(sudoku, row, column + 1));
+                    }
+                }
+                neighbours.sort_unstable();
+                neighbours.dedup();
+                neighbours
+            })
+            .collect::<Vec<_>>();
+
+        let mut cage_of: Vec<Option<usize>> = vec![None; len];
+        let free_neighbours = |cage_of: &[Option<usize>], i: usize| {
+            neighbours[i]
+                .iter()
+                .filter(|n| cage_of[**n].is_none())
+                .count()
+        };
+        // one of the cells with the fewest free neighbours, at random
+        let most_constrained =
+            |cage_of: &[Option<usize>],
+             random: &mut dyn FnMut(usize) -> usize,
+             cells: &mut dyn Iterator<Item = usize>| {
+                let mut best = Vec::new();
+                let mut best_count = usize::MAX;
+                for i in cells {
+                    let count = free_neighbours(cage_of, i);
+                    if count < best_count {
+                        best.clear();
+                        best_count = count;
+                    }
+                    if count == best_count {
+                        best.push(i);
+                    }
+                }
+                (!best.is_empty()).then(|| best[random(best.len())])
+            };
+
+        let mut cages: Vec<Vec<usize>> = Vec::new();
+        while let Some(start) = most_constrained(
+            &cage_of,
+            &mut random,
+            &mut (0..len).filter(|i| cage_of[*i].is_none()),
+        ) {
+            cage_of[start] = Some(cages.len());
+            let mut cells = vec![start];
+            let size = 2 + random(max_size - 1);
+            while cells.len() < size {
+                let mut free = cells
+                    .iter()
+                    .flat_map(|c| neighbours[*c].iter().copied())
+                    .filter(|n| cage_of[*n].is_none())
+                    .filter(|n| cells.iter().all(|c| self.value(*c) != self.value(*n)))
+                    .collect::<Vec<_>>();
+                free.sort_unstable();
+                free.dedup();
+                let Some(next) = most_constrained(&cage_of, &mut random, &mut free.into_iter())
+                else {
+                    break;
+                };
+                cage_of[next] = Some(cages.len());
+                cells.push(next);
+            }
+            cages.push(cells);
+        }
+
+        // let cells that were left over join a cage next to them
+        for k in 0..cages.len() {
+            if cages[k].len() > 1 {
+                continue;
+            }
+            let cell = cages[k][0];
+            let other = neighbours[cell]
+                .iter()
+                .filter_map(|n| cage_of[*n])
+                .find(|c| {
+                    cages[*c].len() > 1
+                        && cages[*c].iter().all(|i| self.value(*i) != self.value(cell))
+                });
+            if let Some(other) = other {
+                cages[other].push(cell);
+                cages[k].clear();
+                cage_of[cell] = Some(other);
+            }
+        }
+
+        cages
+            .into_iter()
+            .filter(|cells| !cells.is_empty())
+            .map(|mut cells| {
+                // the sum goes in the top left cell of the first sudoku the cage is in
+                cells.sort_by_key(|c| {
+                    let p = layout
+                        .positions(*c)
+                        .next()
+                        .expect("every cell is in a sudoku");
+                    (p.sudoku.1, p.sudoku.0, p.row, p.column)
+                });
+                Cage {
+                    sum: cells.iter().map(|c| self.value(*c) as u32).sum(),
+                    cells: cells.into(),
+                }
+            })
+            .collect()
+    }
+
+    /// Remove values of the solved world in a random order, keeping only the givens that
+    /// are still needed next to the cages for a unique solution
+    ///
+    /// `random(n)` should return a random number below `n`. Every check visits at most
+    /// `max_nodes` nodes of the search tree, and a value that could not be checked within
+    /// them is kept.
+    pub fn remove_givens(&mut self, max_nodes: usize, mut random: impl FnMut(usize) -> usize) {
+        let mut indexes = (0..self.cells.len()).collect::<Vec<_>>();
+        while !indexes.is_empty() {
+            let i = indexes.swap_remove(random(indexes.len()));
+            let given = self.cells[i];
+            self.cells[i] = 0;
+            let unique = self
+                .solutions_within(2, max_nodes)
+                .is_some_and(|solutions| solutions.len() == 1);
+            if !unique {
+                self.cells[i] = given;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Predictable stand-in for the random numbers from JavaScript
+    fn counter() -> impl FnMut(usize) -> usize {
+        let mut state = 0usize;
+        move |n| {
+            state = state.wrapping_mul(31).wrapping_add(17) % 1009;
+            state % n
+        }
+    }
+
+    #[test]
+    fn flat_roundtrip() {
+        let cages = vec![
+            Cage {
+                sum: 10,
+                cells: [4, 5, 13].into(),
+            },
+            Cage {
+                sum: 3,
+                cells: [0].into(),
+            },
+        ];
+        let flat = Cage::to_flat(&cages);
+        assert_eq!(&flat[..], [10, 3, 4, 5, 13, 3, 1, 0]);
+        assert_eq!(Cage::from_flat(&flat), Ok(cages));
+        assert_eq!(Cage::from_flat(&[]), Ok(vec![]));
+        assert_eq!(Cage::from_flat(&flat[..7]), Err(CageError::Truncated));
+        assert_eq!(Cage::from_flat(&flat[..4]), Err(CageError::Truncated));
+        assert_eq!(
+            Cage::from_flat(&[1 << 40, 1, 0]),
+            Err(CageError::InvalidSum(1 << 40))
+        );
+    }
+
+    #[test]
+    fn invalid_cages() {
+        let sg = SudokuGrid::new(1, 1);
+        let len = sg.cells.len();
+        let cage = |cells: &[usize]| Cage {
+            sum: 3,
+            cells: cells.into(),
+        };
+        assert_eq!(
+            sg.empty_like().with_cages(vec![cage(&[])]).err(),
+            Some(CageError::Empty)
+        );
+        assert_eq!(
+            sg.empty_like().with_cages(vec![cage(&[0, len])]).err(),
+            Some(CageError::OutOfBounds(len))
+        );
+        assert_eq!(
+            sg.empty_like()
+                .with_cages(vec![cage(&[0, 1]), cage(&[2, 1])])
+                .err(),
+            Some(CageError::Overlapping(1))
+        );
+        assert!(sg.with_cages(vec![cage(&[0, 1]), cage(&[2])]).is_ok());
+    }
+
+    #[test]
+    fn sum_combinations() {
+        let all = 0b11_1111_1110;
+        assert!(sum_is_possible(all, 2, 3)); // 1 + 2
+        assert!(!sum_is_possible(all, 2, 2)); // 1 + 1 repeats a value
+        assert!(sum_is_possible(all, 3, 24)); // 7 + 8 + 9
+        assert!(!sum_is_possible(all, 3, 25));
+        assert!(!sum_is_possible(all & !(1 << 9), 2, 17)); // 8 + 9 without the 9
+        assert!(sum_is_possible(all, 9, 45));
+        assert!(sum_is_possible(0, 0, 0));
+    }
+
+    #[test]
+    fn cage_errors_and_combinations() {
+        let sg = SudokuGrid::new(2, 1);
+        let layout = sg.layout();
+        let cells = [layout.index((0, 0), 0, 0), layout.index((0, 0), 0, 1)];
+        let mut sg = sg
+            .with_cages(vec![Cage {
+                sum: 4,
+                cells: cells.into(),
+            }])
+            .unwrap();
+        assert!(sg.peers(cells[0]).contains(&cells[1]));
+
+        // only 1 + 3 adds up to 4 with different values
+        assert!(sg.cage_allows(cells[0], 1));
+        assert!(!sg.cage_allows(cells[0], 2));
+        assert!(!sg.cage_allows(cells[0], 4));
+        sg.cells[cells[1]] = 3;
+        assert!(sg.cage_allows(cells[0], 1));
+        assert!(!sg.cage_allows(cells[0], 3));
+
+        // a value that is already in the cage is not allowed, even if the sum works out
+        let mut six = SudokuGrid::with_layout(layout.clone())
+            .with_cages(vec![Cage {
+                sum: 6,
+                cells: cells.into(),
+            }])
+            .unwrap();
+        six.cells[cells[1]] = 3;
+        assert!(!six.cage_allows(cells[0], 3));
+
+        sg.cells[cells[0]] = 2;
+        assert!(sg.cage_is_problematic(cells[0]));
+        sg.mark_errors();
+        assert!(sg.cells[cells[1]] & sg.boxes().error_flag() > 0);
+
+        sg.cells[cells[0]] = 1;
+        sg.mark_errors();
+        assert!(sg.cells.iter().all(|c| c & sg.boxes().error_flag() == 0));
+    }
+
+    #[test]
+    fn random_cages_cover_the_world() {
+        let mut sg = SudokuGrid::new(2, 2);
+        sg.cells = sg.solutions(1).remove(0);
+        let cages = sg.random_cages(4, counter());
+
+        let mut cells = cages
+            .iter()
+            .flat_map(|c| c.cells.iter().copied())
+            .collect::<Vec<_>>();
+        cells.sort_unstable();
+        assert_eq!(cells, (0..sg.cells.len()).collect::<Vec<_>>());
+        // only cells that were left over can make a cage larger
+        assert!(cages.iter().all(|c| c.cells.len() <= 5));
+        assert!(cages.iter().filter(|c| c.cells.len() == 1).count() < cages.len() / 10);
+
+        let sg = sg.with_cages(cages).unwrap();
+        assert!(sg.cages_are_solved());
+        assert!(sg.is_solved_all());
+        assert!((0..sg.cells.len()).all(|i| !sg.cage_is_problematic(i)));
+    }
+
+    #[test]
+    fn remove_givens_with_cages() {
+        let mut sg = SudokuGrid::new(1, 1);
+        let solution = sg.solutions(1).remove(0);
+        sg.cells = solution.clone();
+        let cages = sg.random_cages(4, counter());
+        let mut sg = sg.with_cages(cages).unwrap();
+        sg.remove_givens(100_000, counter());
+
+        // the sums of the cages tell most values, so only a few givens are left
+        let givens = sg.cells.iter().filter(|c| **c > 0).count();
+        assert!(givens < 10, "{givens} givens left");
+        assert_eq!(sg.solutions(2), vec![solution]);
+    }
+}
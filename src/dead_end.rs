@@ -0,0 +1,145 @@
+//! Detection of user entries that make the puzzle impossible to finish
+//!
+//! User entries can lead to a dead end without clashing with anything. To find the fewest
+//! entries to take back, the givens are solved while sticking to the user entries wherever
+//! possible: the entries the closest solution disagrees with are the ones to remove.
+
+use crate::solver::Solver;
+use crate::sudokus::SudokuGrid;
+
+#[derive(Debug, PartialEq)]
+pub enum Solvability {
+    /// The user entries can still be completed to a solution
+    Solvable,
+    /// Removing these user entries makes the puzzle solvable again
+    ///
+    /// If `minimal` is false, the solver ran out of nodes before it could check whether
+    /// fewer entries would do.
+    DeadEnd { remove: Vec<usize>, minimal: bool },
+    /// The givens on their own have no solution
+    Unsolvable,
+    /// The solver ran out of nodes before finding any solution
+    Unknown,
+}
+
+impl SudokuGrid {
+    /// Check whether the user entries can still be completed to a solution, visiting at most
+    /// `max_nodes` nodes of the search tree
+    pub fn check_dead_end(&self, max_nodes: usize) -> Solvability {
+        let boxes = self.boxes();
+        let mask = boxes.value_mask();
+        let user = |cell: u8| cell & boxes.user_flag() > 0 && cell & mask != 0;
+        let preferred = self
+            .cells
+            .iter()
+            .map(|c| if user(*c) { c & mask } else { 0 })
+            .collect::<Box<[u8]>>();
+
+        let mut givens = self.empty_like();
+        for (given, cell) in givens.cells.iter_mut().zip(self.cells.iter()) {
+            *given = if user(*cell) { 0 } else { cell & mask };
+        }
+
+        let closest = Solver::new(&givens).closest_solution(&preferred, max_nodes);
+        match (closest.solution, closest.exhausted) {
+            (None, false) => Solvability::Unsolvable,
+            (None, true) => Solvability::Unknown,
+            (Some(_), _) if closest.cost == 0 => Solvability::Solvable,
+            (Some(solution), exhausted) => Solvability::DeadEnd {
+                remove: (0..self.cells.len())
+                    .filter(|i| preferred[*i] > 0 && preferred[*i] != solution[*i])
+                    .collect(),
+                minimal: !exhausted,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Solved world with every third cell left empty
+    fn puzzle() -> (SudokuGrid, Box<[u8]>) {
+        let mut sg = SudokuGrid::new(2, 1);
+        let solution = sg.solutions(1).remove(0);
+        sg.cells = solution.clone();
+        for i in (0..sg.cells.len()).step_by(3) {
+            sg.cells[i] = 0;
+        }
+        (sg, solution)
+    }
+
+    /// Find entries that do not clash with anything, but can not be part of a solution
+    fn dead_ends(sg: &SudokuGrid, solution: &[u8]) -> Vec<(usize, u8)> {
+        let mut dead_ends = Vec::new();
+        for i in (0..sg.cells.len()).step_by(3) {
+            for value in (1..=9).filter(|v| *v != solution[i] && sg.is_candidate(i, *v)) {
+                let mut entered = SudokuGrid::with_layout(sg.layout());
+                entered.cells = sg.cells.clone();
+                entered.cells[i] = value + 16;
+                if entered.solutions(1).is_empty() {
+                    dead_ends.push((i, value + 16));
+                }
+            }
+        }
+        dead_ends
+    }
+
+    #[test]
+    fn solvable_user_entries() {
+        let (mut sg, solution) = puzzle();
+        assert_eq!(sg.check_dead_end(10_000), Solvability::Solvable);
+
+        sg.cells[0] = solution[0] + 16;
+        sg.cells[3] = solution[3] + 16;
+        assert_eq!(sg.check_dead_end(10_000), Solvability::Solvable);
+    }
+
+    #[test]
+    fn remove_fewest_entries() {
+        let (mut sg, solution) = puzzle();
+        let dead_ends = dead_ends(&sg, &solution);
+        let (i, value) = dead_ends[0];
+        let (j, other) = *dead_ends
+            .iter()
+            .find(|(j, _)| sg.sudoku_at_index(*j) != sg.sudoku_at_index(i))
+            .expect("dead ends in both sudokus");
+
+        // correct entries are kept
+        let k = (0..sg.cells.len())
+            .step_by(3)
+            .find(|k| *k != i && *k != j)
+            .unwrap();
+        sg.cells[k] = solution[k] + 16;
+        sg.cells[i] = value;
+        assert_eq!(
+            sg.check_dead_end(100_000),
+            Solvability::DeadEnd {
+                remove: vec![i],
+                minimal: true
+            }
+        );
+
+        sg.cells[j] = other;
+        assert_eq!(
+            sg.check_dead_end(100_000),
+            Solvability::DeadEnd {
+                remove: vec![i.min(j), i.max(j)],
+                minimal: true
+            }
+        );
+
+        // without enough nodes, there is no answer
+        assert_eq!(sg.check_dead_end(1), Solvability::Unknown);
+    }
+
+    #[test]
+    fn unsolvable_givens() {
+        let mut sg = SudokuGrid::new(1, 1);
+        sg.cells[0] = 5;
+        sg.cells[1] = 5;
+        sg.cells[2] = 5 + 16;
+        assert_eq!(sg.check_dead_end(10_000), Solvability::Unsolvable);
+    }
+}
@@ -0,0 +1,284 @@
+//! Text rendering of the whole world with box-drawing characters
+//!
+//! Sudokus are drawn in the same diamond layout as the WebGL view, so shared corner blocks
+//! are drawn where the two sudokus overlap. Every sudoku is labelled with its coordinates
+//! in the gap above its top center block. Blocks that are shared across the edge of the
+//! world are labelled with the neighbour they are shared with as well.
+//!
+//! Givens are shown as a plain number, user entries with a `'`, and errors with a `!`.
+//! The alternate flag (`{:#}`) uses ANSI colours instead: bold givens, blue user entries,
+//! and red errors.
+
+use std::collections::HashMap;
+
+use crate::layout::grid_origin;
+use crate::sudokus::{BoxSize, SudokuGrid, TOP_LEFT_BLOCK};
+
+// directions of lines leaving a position on the canvas
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+/// Box-drawing character for each combination of line directions
+const LINES: [char; 16] = [
+    ' ', '╵', '╷', '│', '╴', '┘', '┐', '┤', '╶', '└', '┌', '├', '─', '┴', '┬', '┼',
+];
+
+/// Get the offset of the unwrapped neighbour each corner block is shared with
+fn corner_neighbours(boxes: BoxSize) -> [(usize, isize, isize); 4] {
+    [
+        (TOP_LEFT_BLOCK, 0, 1),
+        (boxes.top_right_block(), 1, 0),
+        (boxes.bottom_left_block(), -1, 0),
+        (boxes.bottom_right_block(), 0, -1),
+    ]
+}
+
+struct Canvas {
+    boxes: BoxSize,
+    /// Characters per cell (the widest value and a marker)
+    cell_width: isize,
+    /// Line directions at (column, line) positions
+    lines: HashMap<(isize, isize), u8>,
+    /// Text at (column, line) positions, with the width it takes up
+    text: HashMap<(isize, isize), (String, isize)>,
+}
+
+impl Canvas {
+    fn new(boxes: BoxSize) -> Self {
+        Canvas {
+            boxes,
+            cell_width: boxes.values().to_string().len() as isize + 1,
+            lines: HashMap::new(),
+            text: HashMap::new(),
+        }
+    }
+
+    /// Characters per block (including the border on the left)
+    fn block_width(&self) -> isize {
+        self.cell_width * self.boxes.width as isize + 2
+    }
+
+    /// Lines per block (including the border above)
+    fn block_height(&self) -> isize {
+        self.boxes.height as isize + 1
+    }
+
+    /// Get the (column, line) of the top left corner of the block at world position (x, y)
+    fn block_corner(&self, x: isize, y: isize) -> (isize, isize) {
+        (
+            x.div_euclid(self.boxes.width as isize) * self.block_width(),
+            y.div_euclid(self.boxes.height as isize) * self.block_height(),
+        )
+    }
+
+    /// Draw the border of the block with its top left cell at world position (x, y)
+    fn add_block(&mut self, x: isize, y: isize) {
+        let (left, top) = self.block_corner(x, y);
+        let (width, height) = (self.block_width(), self.block_height());
+        for column in left..left + width {
+            for line in [top, top + height] {
+                *self.lines.entry((column, line)).or_default() |= RIGHT;
+                *self.lines.entry((column + 1, line)).or_default() |= LEFT;
+            }
+        }
+        for line in top..top + height {
+            for column in [left, left + width] {
+                *self.lines.entry((column, line)).or_default() |= DOWN;
+                *self.lines.entry((column, line + 1)).or_default() |= UP;
+            }
+        }
+    }
+
+    /// Put text in the cell at world position (x, y)
+    fn add_cell(&mut self, x: isize, y: isize, text: String) {
+        let (left, top) = self.block_corner(x, y);
+        let column = left + 2 + self.cell_width * x.rem_euclid(self.boxes.width as isize);
+        let line = top + 1 + y.rem_euclid(self.boxes.height as isize);
+        self.text.insert((column, line), (text, self.cell_width));
+    }
+
+    /// Put a label in the middle of the block with its top left cell at world position (x, y)
+    fn add_label(&mut self, x: isize, y: isize, label: String) {
+        let width = label.chars().count() as isize;
+        let (left, top) = self.block_corner(x, y);
+        let column = left + (self.block_width() + 1 - width) / 2;
+        let line = top + (self.block_height() + 1) / 2;
+        self.text.insert((column, line), (label, width));
+    }
+
+    fn write(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let positions = || self.lines.keys().chain(self.text.keys());
+        let min_column = positions().map(|(c, _)| *c).min().unwrap_or(0);
+        let max_column = positions().map(|(c, _)| *c).max().unwrap_or(0);
+        let min_line = positions().map(|(_, l)| *l).min().unwrap_or(0);
+        let max_line = positions().map(|(_, l)| *l).max().unwrap_or(0);
+
+        for line in min_line..=max_line {
+            let mut row = String::new();
+            let mut column = min_column;
+            while column <= max_column {
+                if let Some((text, width)) = self.text.get(&(column, line)) {
+                    row.push_str(text);
+                    column += width;
+                } else {
+                    let lines = self.lines.get(&(column, line)).copied().unwrap_or(0);
+                    row.push(LINES[lines as usize]);
+                    column += 1;
+                }
+            }
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl SudokuGrid {
+    /// Text for a cell, as wide as the widest value and a marker
+    fn cell_text(&self, i: usize, colour: bool) -> String {
+        let boxes = self.boxes();
+        let width = boxes.values().to_string().len();
+        let cell = self.cells[i];
+        let value = cell & boxes.value_mask();
+        if value == 0 {
+            return format!("{:>width$} ", ".");
+        }
+        let error = cell & boxes.error_flag() > 0;
+        let user = cell & boxes.user_flag() > 0;
+        let text = format!("{value:>width$}");
+        match (colour, error, user) {
+            (true, true, _) => format!("\x1b[31m{text}\x1b[0m "),
+            (true, false, true) => format!("\x1b[34m{text}\x1b[0m "),
+            (true, false, false) => format!("\x1b[1m{text}\x1b[0m "),
+            (false, true, _) => format!("{text}!"),
+            (false, false, true) => format!("{text}'"),
+            (false, false, false) => format!("{text} "),
+        }
+    }
+}
+
+impl std::fmt::Display for SudokuGrid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let colour = f.alternate();
+        let boxes = self.boxes();
+        let mut canvas = Canvas::new(boxes);
+        let layout = self.layout();
+        let (width, height) = (boxes.width as isize, boxes.height as isize);
+        // the block above the middle of the top row of blocks
+        let label_x = width * (boxes.across() / 2) as isize;
+
+        for (x, y) in self.sudoku_coords() {
+            let s = self.sudoku((x, y));
+            let (ox, oy) = layout.origin((x, y));
+
+            for block in 0..boxes.values() {
+                let (row, column) = boxes.position(block, 0);
+                canvas.add_block(ox + column as isize, oy + row as isize);
+                for (offset, index) in self.block(s, block).indexes().enumerate() {
+                    let (row, column) = boxes.position(block, offset);
+                    let text = self.cell_text(index, colour);
+                    canvas.add_cell(ox + column as isize, oy + row as isize, text);
+                }
+            }
+
+            // the gap above the top center block is not part of another sudoku in the
+            // diamond pattern (but can be in other topologies, or with other blocks)
+            if layout.sudoku_cell_at(ox + label_x, oy - height).is_none() {
+                canvas.add_label(ox + label_x, oy - height, format!("{x},{y}"));
+            }
+
+            // label neighbours across the edge of the world
+            if self.wrap().is_none() {
+                continue;
+            }
+            for (block, dx, dy) in corner_neighbours(boxes) {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                let inside =
+                    (0..self.n as isize).contains(&nx) && (0..self.m as isize).contains(&ny);
+                if let (false, Some((wx, wy))) = (inside, self.shared_with((x, y), block).first()) {
+                    let (nox, noy) = grid_origin(boxes, nx, ny);
+                    canvas.add_label(nox + label_x, noy - height, format!("{wx},{wy}"));
+                }
+            }
+        }
+
+        canvas.write(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{GridTopology, Wrap};
+
+    #[test]
+    fn display_single_sudoku() {
+        let mut sg = SudokuGrid::new(1, 1);
+        let s = sg.sudoku((0, 0)).clone();
+        sg.set_block(&s, crate::sudokus::MIDDLE_CENTER_BLOCK, 1..=9);
+        let i = s.block_start[crate::sudokus::MIDDLE_CENTER_BLOCK];
+        sg.cells[i + 1] += 16; // user entry
+        sg.cells[i + 2] += 16 + 32; // error
+
+        let text = sg.to_string();
+        assert!(text.contains("│ . . . │ 1 2'3!│ . . . │\n"));
+        assert!(text.contains("├───────┼───────┼───────┤\n"));
+        assert_eq!(text.matches("0,0").count(), 5); // itself, and itself as neighbours
+
+        let colour = format!("{sg:#}");
+        assert!(colour.contains("\x1b[1m1\x1b[0m \x1b[34m2\x1b[0m \x1b[31m3\x1b[0m │"));
+    }
+
+    #[test]
+    fn display_world() {
+        let sg = SudokuGrid::new(3, 2);
+        let text = sg.to_string();
+        for x in 0..3 {
+            for y in 0..2 {
+                assert!(text.contains(&format!("{x},{y}")));
+            }
+        }
+        // every cell of the world is drawn at least once
+        assert!(text.matches(". ").count() >= sg.cells.len());
+        // overlapping corner blocks join the borders of both sudokus
+        assert!(text.contains('┼'));
+        assert!(text.contains("┌───────┬───────┼───────┼───────┼───────┼───────┬───────┐\n"));
+        // gray blocks are left empty, with the label of the sudoku drawn around them
+        assert!(
+            text.contains("0,1  │ . . . │ . . . │ . . . │  1,0  │ . . . │ . . . │ . . . │  2,1\n")
+        );
+
+        // a bounded world has no neighbours across the edges to label
+        let sg = SudokuGrid::with_wrap(3, 2, Wrap::Bounded);
+        let text = sg.to_string();
+        assert_eq!(text.matches(',').count(), 3 * 2);
+        assert_eq!(text.matches(". ").count(), sg.cells.len());
+    }
+
+    #[test]
+    fn display_other_box_sizes() {
+        for (boxes, line) in [
+            (BoxSize::new(2, 2).unwrap(), "│ 4'3!│ . . │"),
+            (BoxSize::new(3, 2).unwrap(), "│ 6'5!4 │ . . . │"),
+            (BoxSize::new(4, 4).unwrap(), "│ 16'15!14 13 │  .  .  .  . │"),
+        ] {
+            let topology = GridTopology::new(2, 1, Wrap::Bounded)
+                .with_boxes(boxes)
+                .unwrap();
+            let mut sg = SudokuGrid::with_topology(topology);
+            let s = sg.sudoku((0, 0)).clone();
+            let values = boxes.values() as u8;
+            sg.set_block(&s, TOP_LEFT_BLOCK, (1..=values).rev());
+            let mut block = sg.block(&s, TOP_LEFT_BLOCK).indexes();
+            let (user, error) = (block.next().unwrap(), block.next().unwrap());
+            sg.cells[user] |= boxes.user_flag();
+            sg.cells[error] |= boxes.error_flag();
+
+            let text = sg.to_string();
+            assert!(text.contains(line), "{boxes:?}\n{text}");
+            assert_eq!(text.matches('.').count(), sg.cells.len() - values as usize);
+            assert!(format!("{sg:#}").contains("\x1b[31m"));
+        }
+    }
+}
@@ -0,0 +1,563 @@
+//! Compact URL-safe encoding of a grid state, for save data and share links
+//!
+//! Layout before compression (format version 1):
+//! - version byte
+//! - `n` and `m` as LEB128 varints
+//! - topology byte (0 torus, 1 bounded, 2 Möbius strip, 3 Klein bottle, `4 + k` for the
+//!   preset `Preset::ALL[k]`, `16 + k` for the edge tiling with `EdgeOverlap::ALL[k]`)
+//! - block width and height bytes
+//! - rules byte (see [`Rules::to_bits`])
+//! - plane mask byte: bit `k` is set if flag bit `b + k` is used by any cell, with `b` the
+//!   number of value bits (see [`BoxSize::value_bits`])
+//! - cell values as 4-bit nibbles, two cells per byte (low nibble first), or one byte per
+//!   cell for grids with more than 15 values
+//! - one bitplane (8 cells per byte) for every flag bit in the plane mask
+//! - killer cages: the number of cages, and for every cage its sum, its number of cells, its
+//!   first cell, and the difference of every other cell to the one before it (zigzag
+//!   encoded, so small negative steps stay small), all as varints
+//!
+//! Everything after the version byte is run-length encoded (a zero byte is followed by
+//! the length of the run of zeros minus one), and the result is written as unpadded
+//! base64url so it can be put in a link directly.
+
+use crate::cages::Cage;
+use crate::rules::Rules;
+use crate::sudokus::{BoxSize, SudokuGrid};
+use crate::topology::{EdgeOverlap, EdgeTopology, GridTopology, Preset, Topology, Wrap};
+
+const VERSION: u8 = 1;
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// Character that is not part of the base64url alphabet
+    InvalidCharacter(char),
+    /// Data ended before the state was complete
+    UnexpectedEnd,
+    /// Data was written by a newer (or unknown) encoder
+    UnsupportedVersion(u8),
+    /// Dimensions are zero or too large, or the block size is not supported
+    InvalidSize,
+    /// Topology byte that does not match any topology
+    UnknownTopology(u8),
+    /// Rules byte with rules this version does not know
+    UnknownRules(u8),
+    /// Cages with cells outside the grid, without cells, or sharing cells
+    InvalidCages,
+    /// Data continues after the state was complete
+    TrailingData,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidCharacter(c) => write!(f, "invalid character {c:?} in state"),
+            DecodeError::UnexpectedEnd => write!(f, "state ended unexpectedly"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported state version {v}"),
+            DecodeError::InvalidSize => write!(f, "invalid world size in state"),
+            DecodeError::UnknownTopology(t) => write!(f, "unknown topology {t} in state"),
+            DecodeError::UnknownRules(r) => write!(f, "unknown rules {r} in state"),
+            DecodeError::InvalidCages => write!(f, "invalid killer cages in state"),
+            DecodeError::TrailingData => write!(f, "unexpected data after end of state"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl SudokuGrid {
+    /// Encode the cells (values and flags) of this grid as a short URL-safe string
+    ///
+    /// # Panics
+    ///
+    /// For topologies that are not an n by m world, a preset, or an edge tiling (see
+    /// [`Topology::wrap`], [`Topology::preset`] and [`Topology::edge_overlap`]), which can
+    /// not be saved.
+    pub fn encode_state(&self) -> String {
+        let topology = match (self.wrap(), self.preset(), self.edge_overlap()) {
+            (Some(Wrap::Torus), ..) => 0,
+            (Some(Wrap::Bounded), ..) => 1,
+            (Some(Wrap::Mobius), ..) => 2,
+            (Some(Wrap::KleinBottle), ..) => 3,
+            (None, Some(preset), _) => 4 + Preset::ALL.iter().position(|p| *p == preset).unwrap(),
+            (None, None, Some(overlap)) => {
+                16 + EdgeOverlap::ALL.iter().position(|o| *o == overlap).unwrap()
+            }
+            (None, None, None) => {
+                panic!("only n by m worlds, presets and edge tilings can be saved")
+            }
+        };
+        let boxes = self.boxes();
+        let rules = self.rules();
+        let mut body = Vec::new();
+        write_varint(&mut body, self.n);
+        write_varint(&mut body, self.m);
+        body.push(topology as u8);
+        body.push(boxes.width as u8);
+        body.push(boxes.height as u8);
+        body.push(rules.to_bits());
+
+        let value_bits = boxes.value_bits();
+        let used_flags = self.cells.iter().fold(0, |acc, c| acc | c) >> value_bits;
+        body.push(used_flags);
+
+        if value_bits > 4 {
+            body.extend(self.cells.iter().map(|c| c & boxes.value_mask()));
+        } else {
+            for pair in self.cells.chunks(2) {
+                let low = pair[0] & 15;
+                let high = pair.get(1).map_or(0, |c| c & 15);
+                body.push(low | (high << 4));
+            }
+        }
+
+        for plane in (0..8 - value_bits).filter(|p| used_flags & (1 << p) > 0) {
+            let bit = 1 << (value_bits + plane);
+            for chunk in self.cells.chunks(8) {
+                let packed = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, c)| acc | (((c & bit) > 0) as u8) << i);
+                body.push(packed);
+            }
+        }
+
+        write_varint(&mut body, self.cages().len());
+        for cage in self.cages() {
+            write_varint(&mut body, cage.sum as usize);
+            write_varint(&mut body, cage.cells.len());
+            write_varint(&mut body, cage.cells[0]);
+            for pair in cage.cells.windows(2) {
+                let step = pair[1] as isize - pair[0] as isize;
+                write_varint(
+                    &mut body,
+                    ((step << 1) ^ (step >> (isize::BITS - 1))) as usize,
+                );
+            }
+        }
+
+        let mut bytes = vec![VERSION];
+        bytes.extend(run_length_encode(&body));
+        base64url_encode(&bytes)
+    }
+
+    /// Decode a string created by [`SudokuGrid::encode_state`]
+    pub fn decode_state(state: &str) -> Result<SudokuGrid, DecodeError> {
+        let bytes = base64url_decode(state)?;
+        let (&version, rest) = bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let body = run_length_decode(rest)?;
+        let mut reader = body.iter().copied();
+
+        let n = read_varint(&mut reader)?;
+        let m = read_varint(&mut reader)?;
+        let topology = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        let (preset, overlap) = match topology {
+            0..=3 => (None, None),
+            4..16 => (Preset::ALL.get(topology as usize - 4), None),
+            _ => (None, EdgeOverlap::ALL.get(topology as usize - 16)),
+        };
+        if topology > 3 && preset.is_none() && overlap.is_none() {
+            return Err(DecodeError::UnknownTopology(topology));
+        }
+        if preset.is_some_and(|p| p.size() != (n, m)) {
+            return Err(DecodeError::InvalidSize);
+        }
+        let width = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        let height = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        let boxes = BoxSize {
+            width: width as usize,
+            height: height as usize,
+        };
+        let bits = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        let rules = Rules::from_bits(bits).ok_or(DecodeError::UnknownRules(bits))?;
+        let wrap = [Wrap::Torus, Wrap::Bounded, Wrap::Mobius, Wrap::KleinBottle]
+            .get(topology as usize)
+            .copied();
+        // only n by m worlds have other block sizes, and not with every wrap
+        let grid = match wrap {
+            Some(wrap) => Some(
+                GridTopology::new(n, m, wrap)
+                    .with_boxes(boxes)
+                    .map_err(|_| DecodeError::InvalidSize)?,
+            ),
+            None if boxes != BoxSize::CLASSIC => return Err(DecodeError::InvalidSize),
+            None => None,
+        };
+        let values = boxes.values();
+        n.checked_mul(m)
+            .and_then(|s| s.checked_mul(2 * values * values))
+            .filter(|len| *len > 0)
+            .ok_or(DecodeError::InvalidSize)?;
+        // every sudoku stores at least half of its blocks itself
+        let min_cells = n * m * (values / 2) * values;
+        let min_bytes = if boxes.value_bits() > 4 {
+            min_cells
+        } else {
+            min_cells / 2
+        };
+        if preset.is_none() && min_bytes > body.len() {
+            // don't allocate a huge grid for a truncated (or malicious) state (presets are
+            // small)
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let value_bits = boxes.value_bits();
+        let used_flags = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        if used_flags >> (8 - value_bits) > 0 {
+            return Err(DecodeError::InvalidSize);
+        }
+
+        let mut sg = match (grid, preset, overlap) {
+            (Some(grid), ..) => SudokuGrid::with_topology(grid),
+            (_, Some(preset), _) => SudokuGrid::with_topology(*preset),
+            (_, _, Some(overlap)) => SudokuGrid::with_topology(EdgeTopology::new(n, m, *overlap)),
+            (None, None, None) => return Err(DecodeError::UnknownTopology(topology)),
+        }
+        .with_rules(rules);
+        let len = sg.cells.len();
+        if value_bits > 4 {
+            for cell in sg.cells.iter_mut() {
+                *cell = reader.next().ok_or(DecodeError::UnexpectedEnd)? & boxes.value_mask();
+            }
+        } else {
+            for i in (0..len).step_by(2) {
+                let pair = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+                sg.cells[i] = pair & 15;
+                if i + 1 < len {
+                    sg.cells[i + 1] = pair >> 4;
+                }
+            }
+        }
+
+        for plane in (0..8 - value_bits).filter(|p| used_flags & (1 << p) > 0) {
+            let bit = 1 << (value_bits + plane);
+            for chunk_start in (0..len).step_by(8) {
+                let packed = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+                for i in chunk_start..len.min(chunk_start + 8) {
+                    if packed & (1 << (i - chunk_start)) > 0 {
+                        sg.cells[i] |= bit;
+                    }
+                }
+            }
+        }
+
+        let mut cages = Vec::new();
+        for _ in 0..read_varint(&mut reader)? {
+            let sum = read_varint(&mut reader)?;
+            let count = read_varint(&mut reader)?;
+            let mut cell = read_varint(&mut reader)?;
+            let mut cells = Vec::new();
+            for k in 0..count {
+                if k > 0 {
+                    let zigzag = read_varint(&mut reader)?;
+                    let step = (zigzag >> 1) as isize ^ -((zigzag & 1) as isize);
+                    cell = cell.wrapping_add_signed(step);
+                }
+                cells.push(cell);
+            }
+            cages.push(Cage {
+                sum: u32::try_from(sum).map_err(|_| DecodeError::InvalidCages)?,
+                cells: cells.into(),
+            });
+        }
+        let sg = sg
+            .with_cages(cages)
+            .map_err(|_| DecodeError::InvalidCages)?;
+
+        if reader.next().is_some() {
+            return Err(DecodeError::TrailingData);
+        }
+
+        Ok(sg)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 128 {
+        bytes.push((value & 127) as u8 | 128);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Iterator<Item = u8>) -> Result<usize, DecodeError> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = reader.next().ok_or(DecodeError::UnexpectedEnd)?;
+        value |= ((byte & 127) as usize)
+            .checked_shl(shift)
+            .ok_or(DecodeError::InvalidSize)?;
+        if byte < 128 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::InvalidSize)
+}
+
+/// Replace each run of zeros by a zero followed by the run length minus one
+fn run_length_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0 {
+            let run = bytes[i..].iter().take(256).take_while(|b| **b == 0).count();
+            out.push(0);
+            out.push((run - 1) as u8);
+            i += run;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn run_length_decode(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::with_capacity(bytes.len() * 2);
+    let mut reader = bytes.iter().copied();
+    while let Some(byte) = reader.next() {
+        if byte == 0 {
+            let run = reader.next().ok_or(DecodeError::UnexpectedEnd)? as usize + 1;
+            out.extend(std::iter::repeat_n(0, run));
+        } else {
+            out.push(byte);
+        }
+    }
+    Ok(out)
+}
+
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL[(triple >> (18 - 6 * i)) & 63] as char);
+        }
+    }
+    out
+}
+
+fn base64url_decode(s: &str) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut buffer = 0usize;
+    let mut bits = 0;
+    for c in s.chars() {
+        let value = BASE64URL
+            .iter()
+            .position(|b| *b as char == c)
+            .ok_or(DecodeError::InvalidCharacter(c))?;
+        buffer = (buffer << 6 | value) & 0xffffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64url_roundtrip() {
+        for len in 0..10 {
+            let bytes = (0..len).map(|i| (i * 97 + 13) as u8).collect::<Vec<_>>();
+            let encoded = base64url_encode(&bytes);
+            assert!(!encoded.contains(['+', '/', '=']));
+            assert_eq!(base64url_decode(&encoded), Ok(bytes));
+        }
+        assert_eq!(base64url_encode(b"Man"), "TWFu");
+        assert_eq!(base64url_encode(b"Ma"), "TWE");
+        assert_eq!(
+            base64url_decode("TW+u"),
+            Err(DecodeError::InvalidCharacter('+'))
+        );
+    }
+
+    #[test]
+    fn run_length_roundtrip() {
+        let mut bytes = vec![1, 0, 0, 0, 2, 0, 3];
+        bytes.extend([0; 600]);
+        bytes.push(4);
+        let encoded = run_length_encode(&bytes);
+        assert_eq!(&encoded[..7], [1, 0, 2, 2, 0, 0, 3]);
+        assert_eq!(run_length_decode(&encoded), Ok(bytes));
+    }
+
+    #[test]
+    fn state_roundtrip() {
+        let mut sg = SudokuGrid::new(3, 2);
+        for i in 0..sg.cells.len() {
+            sg.cells[i] = match i % 5 {
+                0 => 0,
+                1 => (i % 9) as u8 + 1,
+                2 => (i % 9) as u8 + 1 + 16,
+                3 => 16,
+                _ => (i % 9) as u8 + 1 + 16 + 32,
+            };
+        }
+
+        let state = sg.encode_state();
+        let decoded = SudokuGrid::decode_state(&state).unwrap();
+        assert_eq!((decoded.n, decoded.m), (3, 2));
+        assert_eq!(decoded.cells, sg.cells);
+
+        let mut sg = SudokuGrid::with_wrap(2, 3, Wrap::Bounded);
+        sg.cells[sg.cells.len() - 1] = 9 + 16;
+        let decoded = SudokuGrid::decode_state(&sg.encode_state()).unwrap();
+        assert_eq!(decoded.wrap(), Some(Wrap::Bounded));
+        assert_eq!(decoded.cells, sg.cells);
+
+        for preset in Preset::ALL {
+            let mut sg = SudokuGrid::with_topology(preset);
+            sg.cells[sg.cells.len() - 1] = 7;
+            let decoded = SudokuGrid::decode_state(&sg.encode_state()).unwrap();
+            assert_eq!((decoded.wrap(), decoded.preset()), (None, Some(preset)));
+            assert_eq!(decoded.cells, sg.cells);
+        }
+
+        for overlap in EdgeOverlap::ALL {
+            let mut sg = SudokuGrid::with_topology(EdgeTopology::new(3, 2, overlap));
+            sg.cells[sg.cells.len() - 1] = 7;
+            let decoded = SudokuGrid::decode_state(&sg.encode_state()).unwrap();
+            assert_eq!((decoded.n, decoded.m), (3, 2));
+            assert_eq!(decoded.edge_overlap(), Some(overlap));
+            assert_eq!(decoded.cells, sg.cells);
+        }
+    }
+
+    #[test]
+    fn state_roundtrip_box_sizes() {
+        for (width, height, wrap) in [
+            (2, 2, Wrap::Torus),
+            (3, 2, Wrap::Bounded),
+            (2, 3, Wrap::Torus),
+            (4, 4, Wrap::KleinBottle),
+        ] {
+            let boxes = BoxSize::new(width, height).unwrap();
+            let topology = GridTopology::new(2, 3, wrap).with_boxes(boxes).unwrap();
+            let mut sg = SudokuGrid::with_topology(topology);
+            let values = boxes.values();
+            for i in 0..sg.cells.len() {
+                let value = (i % values) as u8 + 1;
+                sg.cells[i] = match i % 4 {
+                    0 => 0,
+                    1 => value,
+                    2 => value | boxes.user_flag(),
+                    _ => value | boxes.user_flag() | boxes.wrong_flag(),
+                };
+            }
+
+            let state = sg.encode_state();
+            let decoded = SudokuGrid::decode_state(&state).unwrap();
+            assert_eq!((decoded.boxes(), decoded.wrap()), (boxes, Some(wrap)));
+            assert_eq!(decoded.cells, sg.cells);
+        }
+
+        // blocks that are not square can not be mirrored across a Möbius strip
+        let mut bytes = vec![VERSION];
+        bytes.extend(run_length_encode(&[1, 1, 2, 3, 2, 0, 0]));
+        assert_eq!(
+            SudokuGrid::decode_state(&base64url_encode(&bytes)).err(),
+            Some(DecodeError::InvalidSize)
+        );
+    }
+
+    #[test]
+    fn state_roundtrip_rules() {
+        let rules = Rules {
+            diagonals: true,
+            windows: true,
+            anti_knight: false,
+            anti_king: true,
+        };
+        let mut sg = SudokuGrid::with_wrap(2, 1, Wrap::Bounded).with_rules(rules);
+        sg.cells[3] = 4 + 16;
+        let state = sg.encode_state();
+        let decoded = SudokuGrid::decode_state(&state).unwrap();
+        assert_eq!(
+            (decoded.rules(), decoded.boxes()),
+            (rules, BoxSize::CLASSIC)
+        );
+        assert_eq!(decoded.cells, sg.cells);
+
+        // rules added by a later version
+        let mut bytes = vec![VERSION];
+        bytes.extend(run_length_encode(&[1, 1, 0, 3, 3, 128, 0]));
+        assert_eq!(
+            SudokuGrid::decode_state(&base64url_encode(&bytes)).err(),
+            Some(DecodeError::UnknownRules(128))
+        );
+    }
+
+    #[test]
+    fn state_roundtrip_cages() {
+        let mut sg = SudokuGrid::new(2, 1);
+        sg.cells = sg.solutions(1).remove(0);
+        let cages = sg.random_cages(4, |n| n / 2);
+        let mut sg = sg.with_cages(cages).unwrap();
+        sg.cells[5] = 0;
+        let state = sg.encode_state();
+        let decoded = SudokuGrid::decode_state(&state).unwrap();
+        assert_eq!(decoded.cages(), sg.cages());
+        assert_eq!(decoded.cells, sg.cells);
+
+        // a cell in two cages
+        let mut bytes = vec![VERSION];
+        let mut body = vec![1, 1, 0, 3, 3, 0, 0];
+        body.extend([0; 63 / 2 + 1]);
+        body.extend([2, 3, 2, 7, 2, 4, 1, 7]);
+        bytes.extend(run_length_encode(&body));
+        assert_eq!(
+            SudokuGrid::decode_state(&base64url_encode(&bytes)).err(),
+            Some(DecodeError::InvalidCages)
+        );
+    }
+
+    #[test]
+    fn state_is_compact() {
+        // a 5x5 world with a third of the cells given and no user entries
+        let mut sg = SudokuGrid::new(5, 5);
+        for i in (0..sg.cells.len()).step_by(3) {
+            sg.cells[i] = (i % 9) as u8 + 1;
+        }
+
+        let state = sg.encode_state();
+        assert!(state.len() < sg.cells.len(), "{} chars", state.len());
+        assert_eq!(SudokuGrid::decode_state(&state).unwrap().cells, sg.cells);
+    }
+
+    #[test]
+    fn decode_invalid_state() {
+        assert_eq!(
+            SudokuGrid::decode_state("").err(),
+            Some(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            SudokuGrid::decode_state("Ag").err(),
+            Some(DecodeError::UnsupportedVersion(2))
+        );
+
+        let state = SudokuGrid::new(1, 1).encode_state();
+        assert_eq!(
+            SudokuGrid::decode_state(&state[..state.len() - 2]).err(),
+            Some(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            SudokuGrid::decode_state(&format!("{state}AQEB")).err(),
+            Some(DecodeError::TrailingData)
+        );
+    }
+}
@@ -0,0 +1,861 @@
+//! Mapping between world positions (as drawn by the WebGL view) and cells
+//!
+//! The world is drawn as a diamond pattern of sudokus that repeats every 12x12 cells, in
+//! which every 3x3 block is either part of a sudoku or a gray filler block. World positions
+//! are whole cells, with y pointing down. Sudokus with other block sizes are only found by
+//! their [`Topology::origin`].
+
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::sudokus::{BoxSize, Coords};
+use crate::topology::{SharedBlock, Topology};
+
+/// World position of the top left cell of a sudoku (matching the WebGL view)
+///
+/// Moving one sudoku in the x direction moves 6 cells right and up, moving one sudoku in
+/// the y direction moves 6 cells left and up. Coordinates outside of the world are not
+/// wrapped around, so this can also be used for the copies of sudokus next to the world.
+pub fn sudoku_origin(x: isize, y: isize) -> (isize, isize) {
+    (6 * (x - y), 3 - 6 * (x + y))
+}
+
+/// World position of the top left cell of a sudoku in a grid world: the diamond pattern of
+/// [`sudoku_origin`], stretched to the size of the blocks
+///
+/// The top right block of a sudoku is the bottom left block of the next one in the x
+/// direction.
+pub fn grid_origin(boxes: BoxSize, x: isize, y: isize) -> (isize, isize) {
+    let dx = ((boxes.across() - 1) * boxes.width) as isize;
+    let dy = ((boxes.down() - 1) * boxes.height) as isize;
+    (dx * (x - y), boxes.height as isize - dy * (x + y))
+}
+
+/// Get the sudoku and (column, row) in that sudoku drawn at a world position
+///
+/// Returns `None` for gray filler blocks. Cells in shared corner blocks are returned for
+/// one of the two sudokus only. The sudoku coordinates are not wrapped around to the world
+/// (see [`Layout::sudoku_cell_at`]).
+pub fn sudoku_cell_at(x: isize, y: isize) -> Option<((isize, isize), usize, usize)> {
+    // the sudoku pattern repeats in 12x12 sections of 4x4 blocks
+    let bx = x.rem_euclid(12) / 3;
+    let by = y.rem_euclid(12) / 3;
+    if (bx, by) == (1, 0) || (bx, by) == (3, 2) {
+        return None; // gray block
+    }
+
+    let top_left = (bx == 0 && by < 2) as isize;
+    let top_right = (bx >= 2 && by < 2) as isize;
+    let bottom_right = (bx == 3 && by == 3) as isize;
+
+    // sudokus are on a diagonal grid
+    let (fx, fy) = (x.div_euclid(12), y.div_euclid(12));
+    let sx = fx - fy + top_right;
+    let sy = -fx - fy + top_left - bottom_right;
+
+    let column = (x + 6 * top_left - 6 * top_right - 6 * bottom_right).rem_euclid(12);
+    let row = (y - 3 + 6 * top_left + 6 * top_right - 6 * bottom_right).rem_euclid(12);
+
+    Some(((sx, sy), column as usize, row as usize))
+}
+
+/// Position of a cell in one of the sudokus it is part of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellPosition {
+    pub sudoku: Coords,
+    pub row: usize,
+    pub column: usize,
+}
+
+impl CellPosition {
+    fn in_block(boxes: BoxSize, sudoku: Coords, block: usize, offset: usize) -> Self {
+        let (row, column) = boxes.position(block, offset);
+        CellPosition {
+            sudoku,
+            row,
+            column,
+        }
+    }
+}
+
+/// Where the cells of every sudoku are stored, for a world with any [`Topology`]
+///
+/// This only depends on the topology of the world, so all conversions are done without
+/// needing the cells themselves.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub n: usize,
+    pub m: usize,
+    boxes: BoxSize,
+    topology: Rc<dyn Topology>,
+    /// Position in `blocks` of the sudoku at every coordinate (`x + y * n`)
+    sudokus: Box<[Option<usize>]>,
+    /// First cell of every block of every sudoku (one sudoku after the other), and whether
+    /// it is stored mirrored
+    blocks: Box<[(usize, bool)]>,
+    /// Every sudoku and block that each block of cells is part of, starting with the one
+    /// that stores them (the others are mirrored compared to that one)
+    owners: Box<[Box<[SharedBlock]>]>,
+}
+
+impl Layout {
+    /// Decide where the cells of every sudoku are stored
+    ///
+    /// Sudokus store their blocks in [`BoxSize::block_memory_order`], one after the other
+    /// (by `x + y * n`), skipping the blocks that another sudoku already stored. The blocks
+    /// that are still missing after that (the top left and top right blocks that are not
+    /// shared) are stored last: all top left blocks, then all top right blocks.
+    pub fn new(topology: impl Topology + 'static) -> Self {
+        let (n, m) = topology.size();
+        let boxes = topology.box_size();
+        let values = boxes.values();
+        let mut sudokus = vec![None; n * m];
+        let mut coords = Vec::new();
+        for (x, y) in (0..n * m).map(|i| (i % n, i / n)) {
+            if topology.contains((x, y)) {
+                sudokus[x + y * n] = Some(coords.len());
+                coords.push((x, y));
+            }
+        }
+
+        let mut blocks = vec![vec![None; values]; coords.len()];
+        let mut owners = Vec::new();
+        let in_memory_order = coords.iter().enumerate().flat_map(|(s, sudoku)| {
+            boxes
+                .block_memory_order()
+                .map(move |block| (s, *sudoku, block))
+        });
+        let missing = (0..values).flat_map(|block| {
+            coords
+                .iter()
+                .enumerate()
+                .map(move |(s, sudoku)| (s, *sudoku, block))
+        });
+        for (s, sudoku, block) in in_memory_order.chain(missing) {
+            if blocks[s][block].is_some() {
+                continue;
+            }
+            let start = owners.len() * values;
+            let owner = SharedBlock {
+                sudoku,
+                block,
+                mirrored: false,
+            };
+            let shared = topology.shared_with(sudoku, block);
+            for part in std::iter::once(&owner).chain(shared.iter()) {
+                let other = sudokus[part.sudoku.0 + part.sudoku.1 * n]
+                    .expect("blocks are only shared with existing sudokus");
+                blocks[other][part.block] = Some((start, part.mirrored));
+            }
+            owners.push(std::iter::once(owner).chain(shared).collect());
+        }
+
+        Layout {
+            n,
+            m,
+            boxes,
+            topology: Rc::new(topology),
+            sudokus: sudokus.into(),
+            blocks: blocks
+                .into_iter()
+                .flatten()
+                .map(|b| b.expect("every block is stored"))
+                .collect(),
+            owners: owners.into(),
+        }
+    }
+
+    pub fn topology(&self) -> &dyn Topology {
+        self.topology.as_ref()
+    }
+
+    /// Size of the blocks of every sudoku (see [`Topology::box_size`])
+    pub fn boxes(&self) -> BoxSize {
+        self.boxes
+    }
+
+    /// Number of cells in the world
+    pub fn len(&self) -> usize {
+        self.owners.len() * self.boxes.values()
+    }
+
+    /// Whether there is a sudoku at these coordinates
+    pub fn contains(&self, (x, y): Coords) -> bool {
+        x < self.n && y < self.m && self.sudokus[x + y * self.n].is_some()
+    }
+
+    /// Get the coordinates of every sudoku, ordered by `x + y * n`
+    pub fn sudoku_coords(&self) -> impl Iterator<Item = Coords> + use<'_> {
+        (0..self.n * self.m)
+            .filter(|i| self.sudokus[*i].is_some())
+            .map(|i| (i % self.n, i / self.n))
+    }
+
+    /// Get the position of a sudoku among all sudokus (ordered by `x + y * n`)
+    pub fn sudoku_index(&self, (x, y): Coords) -> Option<usize> {
+        debug_assert!(x < self.n && y < self.m, "sudoku coordinates out of bounds");
+        self.sudokus[x + y * self.n]
+    }
+
+    fn sudoku_blocks(&self, coords: Coords) -> &[(usize, bool)] {
+        let s = self.sudoku_index(coords);
+        let s = s.unwrap_or_else(|| panic!("There is no sudoku at {coords:?}"));
+        let values = self.boxes.values();
+        &self.blocks[s * values..(s + 1) * values]
+    }
+
+    /// Whether block i of a sudoku is stored mirrored (when it is shared with a sudoku
+    /// across a twisted join)
+    pub fn is_mirrored(&self, coords: Coords, i: usize) -> bool {
+        self.sudoku_blocks(coords)[i].1
+    }
+
+    /// Get the other sudokus that block i of a sudoku is shared with
+    pub fn shared_with(&self, coords: Coords, i: usize) -> Vec<Coords> {
+        let shared = self.topology.shared_with(coords, i);
+        shared.into_iter().map(|s| s.sudoku).collect()
+    }
+
+    /// Get the index of the first cell of block i of a sudoku
+    pub fn block_start(&self, coords: Coords, i: usize) -> usize {
+        debug_assert!(i < self.boxes.values(), "block index out of bounds");
+        self.sudoku_blocks(coords)[i].0
+    }
+
+    /// Get the cell index at a row and column of a sudoku
+    pub fn index(&self, coords: Coords, row: usize, column: usize) -> usize {
+        let values = self.boxes.values();
+        debug_assert!(
+            row < values && column < values,
+            "cell position out of bounds"
+        );
+        let (block, offset) = self.boxes.block_and_offset(row, column);
+        if self.is_mirrored(coords, block) {
+            self.block_start(coords, block) + self.boxes.mirror(offset)
+        } else {
+            self.block_start(coords, block) + offset
+        }
+    }
+
+    /// Get the position of a cell index in every sudoku it is part of, starting with the
+    /// sudoku that stores it
+    pub fn positions(&self, i: usize) -> impl Iterator<Item = CellPosition> + use<'_> {
+        let values = self.boxes.values();
+        self.owners[i / values].iter().map(move |part| {
+            let offset = if part.mirrored {
+                self.boxes.mirror(i % values)
+            } else {
+                i % values
+            };
+            CellPosition::in_block(self.boxes, part.sudoku, part.block, offset)
+        })
+    }
+
+    /// Get the sudoku in the world and the (column, row) in it, for a position in a sudoku
+    /// that can be outside of the world
+    ///
+    /// Returns `None` if there is no such cell (past an edge that is not joined).
+    fn wrap(&self, (x, y): (isize, isize), column: usize, row: usize) -> Option<CellPosition> {
+        let position = |coords, column, row| self.wrapped_position(coords, column, row);
+        position((x, y), column, row).or_else(|| {
+            // corner blocks are part of a sudoku next to it as well
+            let (dx, dy) = match (column / 3, row / 3) {
+                (0, 0) => (0, 1),
+                (2, 0) => (1, 0),
+                (0, 2) => (-1, 0),
+                (2, 2) => (0, -1),
+                _ => return None,
+            };
+            let column = (column as isize + 6 * (dy - dx)) as usize;
+            let row = (row as isize + 6 * (dx + dy)) as usize;
+            position((x + dx, y + dy), column, row)
+        })
+    }
+
+    /// Get the sudoku in the world and the (column, row) in it, for a position in the sudoku
+    /// drawn at sudoku coordinates that can be outside of the world (see
+    /// [`Topology::sudoku_at`])
+    fn wrapped_position(
+        &self,
+        coords: (isize, isize),
+        column: usize,
+        row: usize,
+    ) -> Option<CellPosition> {
+        let (sudoku, mirrored) = self.topology.sudoku_at(coords)?;
+        let last = self.boxes.values() - 1;
+        let (column, row) = if mirrored {
+            (last - row, last - column)
+        } else {
+            (column, row)
+        };
+        Some(CellPosition {
+            sudoku,
+            row,
+            column,
+        })
+    }
+
+    /// The diamond pattern of [`sudoku_cell_at`], which only has sudokus with 3x3 blocks
+    fn diamond_cell_at(&self, x: isize, y: isize) -> Option<((isize, isize), usize, usize)> {
+        if self.boxes == BoxSize::CLASSIC {
+            sudoku_cell_at(x, y)
+        } else {
+            None
+        }
+    }
+
+    /// World position of the top left cell of a sudoku (see [`Topology::origin`])
+    pub fn origin(&self, coords: Coords) -> (isize, isize) {
+        self.topology.origin(coords)
+    }
+
+    /// Get the sudoku in the world and the (column, row) in it drawn at a world position
+    ///
+    /// Returns `None` for gray filler blocks, and past edges of the world that are not
+    /// joined.
+    pub fn sudoku_cell_at(&self, x: isize, y: isize) -> Option<CellPosition> {
+        self.diamond_cell_at(x, y)
+            .and_then(|(coords, column, row)| self.wrap(coords, column, row))
+            .or_else(|| {
+                // sudokus that are not drawn in the diamond pattern
+                let values = 0..self.boxes.values() as isize;
+                self.sudoku_coords().find_map(|sudoku| {
+                    let (ox, oy) = self.origin(sudoku);
+                    let (column, row) = (x - ox, y - oy);
+                    (values.contains(&column) && values.contains(&row)).then_some(CellPosition {
+                        sudoku,
+                        row: row as usize,
+                        column: column as usize,
+                    })
+                })
+            })
+    }
+
+    /// Get the smallest and largest world positions of the cells of the sudokus, drawn at
+    /// their [`Layout::origin`]
+    fn bounds(&self) -> ((isize, isize), (isize, isize)) {
+        let last = self.boxes.values() as isize - 1;
+        let start = ((isize::MAX, isize::MAX), (isize::MIN, isize::MIN));
+        self.sudoku_coords().map(|coords| self.origin(coords)).fold(
+            start,
+            |((left, top), (right, bottom)), (x, y)| {
+                (
+                    (left.min(x), top.min(y)),
+                    (right.max(x + last), bottom.max(y + last)),
+                )
+            },
+        )
+    }
+
+    /// Get the index of the cell drawn at a world position (`None` for gray filler blocks)
+    pub fn cell_at_world(&self, x: isize, y: isize) -> Option<usize> {
+        let p = self.sudoku_cell_at(x, y)?;
+        Some(self.index(p.sudoku, p.row, p.column))
+    }
+
+    /// Get the index of the cell (dx, dy) cells away from a cell position, as drawn in the
+    /// world (`None` for gray filler blocks and past edges that are not joined)
+    ///
+    /// Unlike [`Layout::cell_at_world`], this also follows the joins of a grid world with
+    /// blocks other than 3x3, by looking for the cell in the sudokus drawn around this one.
+    pub fn cell_at_offset(&self, p: CellPosition, (dx, dy): (isize, isize)) -> Option<usize> {
+        let values = 0..self.boxes.values() as isize;
+        let inside =
+            |(column, row): (isize, isize)| values.contains(&column) && values.contains(&row);
+        let (column, row) = (p.column as isize + dx, p.row as isize + dy);
+        if inside((column, row)) {
+            return Some(self.index(p.sudoku, row as usize, column as usize));
+        }
+        let (ox, oy) = self.origin(p.sudoku);
+        if self.topology.wrap().is_none() {
+            return self.cell_at_world(ox + column, oy + row);
+        }
+
+        // the sudokus around this one in the stretched diamond pattern of a grid world
+        let (x, y) = (p.sudoku.0 as isize, p.sudoku.1 as isize);
+        let mut around = (-1..=1).flat_map(|ay| (-1..=1).map(move |ax| (x + ax, y + ay)));
+        around.find_map(|coords| {
+            let (nx, ny) = grid_origin(self.boxes, coords.0, coords.1);
+            let (column, row) = (ox + column - nx, oy + row - ny);
+            if !inside((column, row)) {
+                return None;
+            }
+            let p = self.wrapped_position(coords, column as usize, row as usize)?;
+            Some(self.index(p.sudoku, p.row, p.column))
+        })
+    }
+
+    /// Get every world position a cell is drawn at, when each sudoku is drawn at its
+    /// [`Layout::origin`]
+    ///
+    /// A torus world repeats itself, so the cell is drawn at these positions shifted by
+    /// multiples of `(6n, -6n)` and `(-6m, -6m)` as well.
+    pub fn world_positions(&self, i: usize) -> Vec<(isize, isize)> {
+        let mut positions = self
+            .positions(i)
+            .map(|p| {
+                let (ox, oy) = self.origin(p.sudoku);
+                (ox + p.column as isize, oy + p.row as isize)
+            })
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+}
+
+/// Direction to move in on the screen
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+impl Layout {
+    /// Get the cell next to cell i in a direction, as seen in the sudoku `in_sudoku`, and
+    /// the sudoku to look at it in
+    ///
+    /// A cell in a shared corner block can be drawn at two places (when its sudokus are
+    /// on opposite edges of the world), so `in_sudoku` picks which one to move from.
+    /// Gaps between the sudokus of the world (like gray filler blocks) are skipped over, and
+    /// at an edge that is not joined the cell stays where it is. The returned sudoku stays
+    /// `in_sudoku` whenever the new cell is part of it. Moves always start from where
+    /// `in_sudoku` is drawn inside the world, so after crossing a twisted join, the next move
+    /// continues in the unmirrored copy of the sudoku.
+    pub fn neighbor(&self, i: usize, direction: Direction, in_sudoku: Coords) -> (usize, Coords) {
+        let mut positions = self.positions(i);
+        let owner = positions.next().expect("every cell is part of a sudoku");
+        let from = std::iter::once(owner)
+            .chain(positions)
+            .find(|p| p.sudoku == in_sudoku)
+            .unwrap_or(owner);
+        let (ox, oy) = self.origin(from.sudoku);
+
+        // a joined edge is filled in up to a sudoku past the edge (apart from gray blocks),
+        // so anything missing within that distance of the sudokus is a gap between them
+        let size = self.boxes.values() as isize;
+        let ((left, top), (right, bottom)) = self.bounds();
+        let xs = left - size..=right + size;
+        let ys = top - size..=bottom + size;
+
+        let (dx, dy) = direction.offset();
+        let (mut x, mut y) = (ox + from.column as isize, oy + from.row as isize);
+        loop {
+            (x, y) = (x + dx, y + dy);
+            let Some(p) = self.sudoku_cell_at(x, y) else {
+                if xs.contains(&x) && ys.contains(&y) {
+                    continue; // gap
+                }
+                return (i, from.sudoku); // edge of a bounded world
+            };
+            let j = self.index(p.sudoku, p.row, p.column);
+
+            // stay in the same sudoku if possible
+            let (column, row) = (x - ox, y - oy);
+            let values = 0..self.boxes.values() as isize;
+            if values.contains(&column)
+                && values.contains(&row)
+                && self.index(from.sudoku, row as usize, column as usize) == j
+            {
+                return (j, from.sudoku);
+            }
+            return (j, p.sudoku);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sudokus::{
+        SudokuGrid, BOTTOM_LEFT_BLOCK, BOTTOM_RIGHT_BLOCK, TOP_LEFT_BLOCK, TOP_RIGHT_BLOCK,
+    };
+    use crate::topology::{EdgeOverlap, EdgeTopology, GridTopology, Preset, Wrap};
+
+    #[test]
+    fn sudoku_origin_shares_corner_blocks() {
+        // top right block of (0, 0) is bottom left block of (1, 0)
+        let (x, y) = sudoku_origin(0, 0);
+        assert_eq!(sudoku_origin(1, 0), (x + 6, y - 6));
+        // top left block of (0, 0) is bottom right block of (0, 1)
+        assert_eq!(sudoku_origin(0, 1), (x - 6, y - 6));
+    }
+
+    #[test]
+    fn gray_blocks() {
+        let mut gray = 0;
+        for x in -12..12 {
+            for y in -12..12 {
+                if sudoku_cell_at(x, y).is_none() {
+                    gray += 1;
+                }
+            }
+        }
+        // 2 out of every 16 blocks are gray
+        assert_eq!(gray, 24 * 24 * 2 / 16);
+        assert_eq!(sudoku_cell_at(3, 0), None);
+        assert_eq!(sudoku_cell_at(9, 6), None);
+        assert_eq!(sudoku_cell_at(3 - 12, 24), None);
+    }
+
+    #[test]
+    fn block_start_matches_grid() {
+        for (n, m) in [(1, 1), (2, 1), (3, 2), (4, 4)] {
+            let sg = SudokuGrid::new(n, m);
+            for x in 0..n {
+                for y in 0..m {
+                    for block in 0..9 {
+                        let start = sg.sudoku((x, y)).block_start[block];
+                        assert_eq!(sg.layout().block_start((x, y), block), start);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn positions_roundtrip() {
+        for (n, m) in [(1, 1), (2, 1), (3, 2)] {
+            let layout = Layout::new(GridTopology::new(n, m, Wrap::Torus));
+            for i in 0..7 * 9 * n * m {
+                let positions = layout.positions(i).collect::<Vec<_>>();
+                assert!(positions.len() <= 2);
+                for p in positions.iter() {
+                    assert_eq!(layout.index(p.sudoku, p.row, p.column), i);
+                }
+                if let [owner, other] = positions[..] {
+                    assert_ne!((owner.row, owner.column), (other.row, other.column));
+                }
+            }
+        }
+
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Torus));
+        let i = layout.index((2, 1), 0, 8); // top right block, stored by (0, 1)
+        let positions = layout.positions(i).collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                CellPosition {
+                    sudoku: (0, 1),
+                    row: 6,
+                    column: 2
+                },
+                CellPosition {
+                    sudoku: (2, 1),
+                    row: 0,
+                    column: 8
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn cell_at_sudoku_origin() {
+        let sg = SudokuGrid::new(3, 2);
+        // including sudokus outside of the world, which wrap around
+        for x in -3..6isize {
+            for y in -2..4isize {
+                let coords = (x.rem_euclid(3) as usize, y.rem_euclid(2) as usize);
+                let s = sg.sudoku(coords);
+                let (ox, oy) = sudoku_origin(x, y);
+                for row in 0..9 {
+                    for (column, i) in sg.row(s, row).indexes().enumerate() {
+                        let (wx, wy) = (ox + column as isize, oy + row as isize);
+                        assert_eq!(
+                            sg.layout().cell_at_world(wx, wy),
+                            Some(i),
+                            "at ({wx}, {wy})"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_in_sudoku() {
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Torus));
+        let i = layout.index((1, 1), 4, 4);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (1, 1)),
+            (layout.index((1, 1), 4, 5), (1, 1))
+        );
+        assert_eq!(
+            layout.neighbor(i, Direction::Up, (1, 1)),
+            (layout.index((1, 1), 3, 4), (1, 1))
+        );
+
+        // into the top right block, which is shared with (2, 1)
+        let i = layout.index((1, 1), 3, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Up, (1, 1)),
+            (layout.index((1, 1), 2, 8), (1, 1))
+        );
+    }
+
+    #[test]
+    fn neighbor_across_seams() {
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Torus));
+
+        // right of the middle right block is a gray block
+        let i = layout.index((0, 0), 4, 8);
+        let (j, coords) = layout.neighbor(i, Direction::Right, (0, 0));
+        let (ox, oy) = sudoku_origin(0, 0);
+        assert_eq!(layout.cell_at_world(ox + 12, oy + 4), Some(j));
+        assert_ne!(coords, (0, 0));
+
+        // from the top right block of (0, 0) into (1, 0)
+        let i = layout.index((0, 0), 0, 8);
+        let (j, coords) = layout.neighbor(i, Direction::Up, (0, 0));
+        assert_eq!(coords, (1, 0));
+        assert_eq!(j, layout.index((1, 0), 5, 2));
+
+        // across the edge of the world: the top right block of (2, 0) wraps to (0, 0)
+        let i = layout.index((2, 0), 2, 8);
+        let (j, coords) = layout.neighbor(i, Direction::Right, (2, 0));
+        assert_eq!(coords, (0, 0));
+        assert_eq!(j, layout.index((0, 0), 8, 3));
+    }
+
+    #[test]
+    fn neighbor_back_and_forth() {
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Torus));
+        for i in 0..7 * 9 * 3 * 2 {
+            for from in layout.positions(i) {
+                for (there, back) in [
+                    (Direction::Right, Direction::Left),
+                    (Direction::Down, Direction::Up),
+                ] {
+                    let (j, coords) = layout.neighbor(i, there, from.sudoku);
+                    assert_eq!(layout.neighbor(j, back, coords).0, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn world_positions_roundtrip() {
+        let sg = SudokuGrid::new(3, 2);
+        for i in 0..sg.cells.len() {
+            let positions = sg.layout().world_positions(i);
+            let shared = sg.sudokus_at_index(i).len() > 1;
+            assert!(positions.len() == 1 || (shared && positions.len() == 2));
+            for (x, y) in positions {
+                assert_eq!(sg.layout().cell_at_world(x, y), Some(i));
+            }
+        }
+
+        // shared blocks inside the world are drawn once, across the edge they are drawn twice
+        let s = sg.sudoku((0, 0));
+        let top_right = s.block_start[crate::sudokus::TOP_RIGHT_BLOCK];
+        assert_eq!(sg.layout().world_positions(top_right).len(), 1);
+        let bottom_left = s.block_start[crate::sudokus::BOTTOM_LEFT_BLOCK];
+        assert_eq!(sg.layout().world_positions(bottom_left).len(), 2);
+    }
+
+    #[test]
+    fn bounded_world() {
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Bounded));
+        assert_eq!(layout.len(), (7 * 3 * 2 + 3 + 2) * 9);
+        assert_eq!(layout.shared_with((1, 1), TOP_LEFT_BLOCK), []);
+        assert_eq!(layout.shared_with((2, 0), TOP_RIGHT_BLOCK), []);
+        assert_eq!(layout.shared_with((0, 1), BOTTOM_LEFT_BLOCK), []);
+        assert_eq!(layout.shared_with((1, 0), BOTTOM_RIGHT_BLOCK), []);
+        assert_eq!(layout.shared_with((1, 1), BOTTOM_RIGHT_BLOCK), [(1, 0)]);
+
+        // every cell is stored once, and unshared corner blocks are not part of another sudoku
+        for i in 0..layout.len() {
+            for p in layout.positions(i) {
+                assert_eq!(layout.index(p.sudoku, p.row, p.column), i);
+            }
+            for (x, y) in layout.world_positions(i) {
+                assert_eq!(layout.cell_at_world(x, y), Some(i));
+            }
+        }
+        let i = layout.index((2, 1), 0, 0);
+        assert_eq!(i, 7 * 3 * 2 * 9 + 2 * 9);
+        assert_eq!(layout.positions(i).count(), 1);
+
+        // nothing is drawn outside of the world
+        let (ox, oy) = sudoku_origin(2, 1);
+        assert_eq!(layout.cell_at_world(ox + 10, oy + 1), None);
+        assert_eq!(layout.cell_at_world(ox - 1, oy + 1), None);
+        // except the corner blocks of sudokus inside it
+        assert_eq!(layout.cell_at_world(ox, oy), Some(i));
+    }
+
+    #[test]
+    fn neighbor_at_bounded_edge() {
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Bounded));
+        let i = layout.index((2, 0), 2, 8);
+        assert_eq!(layout.neighbor(i, Direction::Right, (2, 0)), (i, (2, 0)));
+        let i = layout.index((0, 1), 0, 4);
+        assert_eq!(layout.neighbor(i, Direction::Up, (0, 1)), (i, (0, 1)));
+
+        // inside the world, seams are crossed like in a torus
+        let i = layout.index((0, 0), 0, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Up, (0, 0)),
+            (layout.index((1, 0), 5, 2), (1, 0))
+        );
+    }
+
+    #[test]
+    fn neighbor_in_samurai() {
+        let layout = Layout::new(Preset::Samurai);
+
+        // across the gray block between the two lower sudokus
+        let i = layout.index((0, 1), 4, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (0, 1)),
+            (layout.index((1, 0), 4, 0), (1, 0))
+        );
+        // from the center sudoku into a corner sudoku
+        let i = layout.index((1, 1), 8, 1);
+        assert_eq!(
+            layout.neighbor(i, Direction::Down, (1, 1)),
+            (layout.index((0, 1), 3, 7), (0, 1))
+        );
+        // past the gray block right of the center sudoku there is no sudoku
+        let i = layout.index((1, 1), 4, 8);
+        assert_eq!(layout.neighbor(i, Direction::Right, (1, 1)), (i, (1, 1)));
+        let i = layout.index((1, 1), 8, 4);
+        assert_eq!(layout.neighbor(i, Direction::Down, (1, 1)), (i, (1, 1)));
+    }
+
+    #[test]
+    fn neighbor_in_square_presets() {
+        let layout = Layout::new(Preset::Butterfly);
+        let i = layout.index((0, 0), 0, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (0, 0)),
+            (layout.index((1, 0), 0, 6), (1, 0))
+        );
+        let i = layout.index((0, 0), 8, 0);
+        assert_eq!(
+            layout.neighbor(i, Direction::Down, (0, 0)),
+            (layout.index((0, 1), 6, 0), (0, 1))
+        );
+        let i = layout.index((1, 0), 0, 8);
+        assert_eq!(layout.neighbor(i, Direction::Right, (1, 0)), (i, (1, 0)));
+
+        // the pinwheel leaves empty corners, which are not crossed
+        let layout = Layout::new(Preset::Kazaguruma);
+        let i = layout.index((1, 0), 8, 0);
+        assert_eq!(
+            layout.neighbor(i, Direction::Down, (1, 0)),
+            (layout.index((0, 1), 0, 3), (0, 1))
+        );
+        let i = layout.index((1, 0), 4, 0);
+        assert_eq!(layout.neighbor(i, Direction::Left, (1, 0)), (i, (1, 0)));
+        let i = layout.index((0, 1), 0, 1);
+        assert_eq!(layout.neighbor(i, Direction::Up, (0, 1)), (i, (0, 1)));
+        let i = layout.index((2, 1), 8, 7);
+        assert_eq!(layout.neighbor(i, Direction::Down, (2, 1)), (i, (2, 1)));
+    }
+
+    #[test]
+    fn neighbor_in_edge_worlds() {
+        let layout = Layout::new(EdgeTopology::new(2, 2, EdgeOverlap::Band));
+        let i = layout.index((0, 0), 4, 5);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (0, 0)),
+            (layout.index((0, 0), 4, 6), (0, 0))
+        );
+        let i = layout.index((0, 0), 4, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (0, 0)),
+            (layout.index((1, 0), 4, 3), (1, 0))
+        );
+        let i = layout.index((1, 0), 4, 8);
+        assert_eq!(layout.neighbor(i, Direction::Right, (1, 0)), (i, (1, 0)));
+        let i = layout.index((0, 1), 8, 4);
+        assert_eq!(layout.neighbor(i, Direction::Down, (0, 1)), (i, (0, 1)));
+
+        // the gap of one block between sudokus sharing only an edge block is skipped over
+        let layout = Layout::new(EdgeTopology::new(2, 2, EdgeOverlap::Block));
+        let i = layout.index((0, 0), 0, 8);
+        assert_eq!(
+            layout.neighbor(i, Direction::Right, (0, 0)),
+            (layout.index((1, 0), 0, 0), (1, 0))
+        );
+        let i = layout.index((1, 0), 8, 4);
+        assert_eq!(
+            layout.neighbor(i, Direction::Down, (1, 0)),
+            (layout.index((1, 1), 0, 4), (1, 1))
+        );
+        let i = layout.index((1, 1), 4, 8);
+        assert_eq!(layout.neighbor(i, Direction::Right, (1, 1)), (i, (1, 1)));
+    }
+
+    #[test]
+    fn twisted_worlds() {
+        for wrap in [Wrap::Mobius, Wrap::KleinBottle] {
+            for (n, m) in [(3, 2), (2, 3), (1, 2)] {
+                let sg = SudokuGrid::with_wrap(n, m, wrap);
+                let layout = sg.layout();
+                for i in 0..layout.len() {
+                    for p in layout.positions(i) {
+                        assert_eq!(layout.index(p.sudoku, p.row, p.column), i);
+                    }
+                }
+
+                // every drawn copy has the rows of the sudoku, or its columns when mirrored
+                let ys = match wrap {
+                    Wrap::KleinBottle => -(m as isize)..2 * m as isize,
+                    _ => 0..m as isize,
+                };
+                for x in -(n as isize)..2 * n as isize {
+                    for y in ys.clone() {
+                        let (ox, oy) = sudoku_origin(x, y);
+                        let center = layout.sudoku_cell_at(ox + 4, oy + 4).unwrap();
+                        let s = sg.sudoku(center.sudoku);
+                        let mirrored = x.div_euclid(n as isize) % 2 != 0;
+                        for r in 0..9 {
+                            let mut drawn = (0..9)
+                                .map(|c| layout.cell_at_world(ox + c, oy + r).unwrap())
+                                .collect::<Vec<_>>();
+                            let mut expected = if mirrored {
+                                sg.column(s, 8 - r as usize).indexes().collect::<Vec<_>>()
+                            } else {
+                                sg.row(s, r as usize).indexes().collect::<Vec<_>>()
+                            };
+                            drawn.sort_unstable();
+                            expected.sort_unstable();
+                            assert_eq!(drawn, expected, "{wrap:?} {n}x{m} ({x}, {y})");
+                        }
+                    }
+                }
+            }
+        }
+
+        // the top right block of the right column is the mirrored bottom left block of the
+        // sudoku at the other end of the left column
+        let layout = Layout::new(GridTopology::new(3, 2, Wrap::Mobius));
+        assert_eq!(layout.shared_with((2, 0), TOP_RIGHT_BLOCK), [(0, 1)]);
+        assert_eq!(layout.shared_with((0, 1), BOTTOM_LEFT_BLOCK), [(2, 0)]);
+        assert!(layout.is_mirrored((2, 0), TOP_RIGHT_BLOCK));
+        assert_eq!(layout.index((2, 0), 0, 6), layout.index((0, 1), 8, 2));
+        assert_eq!(layout.shared_with((1, 1), TOP_LEFT_BLOCK), []);
+        assert_eq!(layout.len(), (7 * 3 * 2 + 3) * 9);
+        assert_eq!(
+            Layout::new(GridTopology::new(3, 2, Wrap::KleinBottle)).len(),
+            7 * 3 * 2 * 9
+        );
+    }
+}
@@ -1,7 +1,27 @@
+mod cages;
+mod dead_end;
+mod display;
+mod encoding;
+mod layout;
+mod puzzle;
+mod render;
+mod rules;
+mod solver;
 mod sudokus;
-use sudokus::{DfsBlock, SudokuGrid, BOTTOM_LEFT_BLOCK, BOTTOM_RIGHT_BLOCK, TOP_RIGHT_BLOCK};
+mod svg;
+mod text;
+mod topology;
+use std::rc::Rc;
 
-use wasm_bindgen::prelude::wasm_bindgen;
+use cages::Cage;
+use layout::{Direction, Layout};
+use rules::Rules;
+use sudokus::{
+    BoxSize, DfsBlock, SudokuGrid, BOTTOM_LEFT_BLOCK, BOTTOM_RIGHT_BLOCK, TOP_RIGHT_BLOCK,
+};
+use topology::{EdgeOverlap, EdgeTopology, GridTopology, Preset, Wrap};
+
+use wasm_bindgen::prelude::{wasm_bindgen, JsError};
 
 #[wasm_bindgen]
 extern "C" {
@@ -21,10 +41,10 @@ pub fn random_int(max: usize) -> usize {
     ((max as f64) * random()) as usize
 }
 
-/// Get randomly shuffled 1..=9
-pub fn generate_random_sequence() -> impl Iterator<Item = u8> {
-    let mut values: Vec<u8> = (1..=9).collect();
-    (0..9).map(move |_| {
+/// Get randomly shuffled 1..=values
+pub fn generate_random_sequence(values: usize) -> impl Iterator<Item = u8> {
+    let mut values: Vec<u8> = (1..=values as u8).collect();
+    (0..values.len()).map(move |_| {
         if values.len() > 1 {
             values.swap_remove(random_int(values.len()))
         } else {
@@ -33,55 +53,235 @@ pub fn generate_random_sequence() -> impl Iterator<Item = u8> {
     })
 }
 
+/// Layout of a world of n by m sudokus (sharing corners, or edges if `edges` is set), or of
+/// a preset (which has its own size)
+///
+/// Only worlds sharing corners can wrap around (`wrap`) and have blocks other than 3x3
+/// (`boxes`). Fails for blocks the world can not have (see
+/// [`GridTopology::with_boxes`]), and for options the world does not have.
+fn world_layout(
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<Layout, JsError> {
+    let other_world = match (preset, edges) {
+        (Some(_), Some(_)) => return Err(JsError::new("presets can not share edges")),
+        (Some(_), None) => Some("presets"),
+        (None, Some(_)) => Some("worlds sharing edges"),
+        (None, None) => None,
+    };
+    if let Some(world) = other_world {
+        if wrap.is_some() {
+            return Err(JsError::new(&format!("{world} can not wrap around")));
+        }
+        if boxes.is_some_and(|boxes| boxes != BoxSize::CLASSIC) {
+            return Err(JsError::new(&format!("{world} only have 3x3 blocks")));
+        }
+    }
+
+    Ok(match (preset, edges) {
+        (Some(preset), _) => Layout::new(preset),
+        (None, Some(overlap)) => Layout::new(EdgeTopology::new(n, m, overlap)),
+        (None, None) => Layout::new(
+            GridTopology::new(n, m, wrap.unwrap_or_default())
+                .with_boxes(boxes.unwrap_or_default())?,
+        ),
+    })
+}
+
+/// Empty grid for a world of n by m sudokus (sharing corners, or edges if `edges` is set),
+/// or for a preset (which has its own size)
+fn world(
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<SudokuGrid, JsError> {
+    let layout = world_layout(n, m, wrap, preset, edges, boxes)?;
+    Ok(SudokuGrid::with_layout(Rc::new(layout)))
+}
+
 /// Generate a solved sudoku based on random input data
 #[wasm_bindgen]
-pub fn generate(n: usize, m: usize, make_puzzle: bool) -> Box<[u8]> {
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn generate(
+    n: usize,
+    m: usize,
+    make_puzzle: bool,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<Box<[u8]>, JsError> {
     console_error_panic_hook::set_once();
 
-    let mut sg = SudokuGrid::new(n, m);
+    let mut sg = generate_solution(
+        world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default()),
+    )?;
+    if make_puzzle {
+        punch_holes(&mut sg);
+    }
+
+    Ok(sg.cells)
+}
+
+/// Puzzle together with the solution it was generated from
+#[wasm_bindgen]
+pub struct Puzzle {
+    cells: Box<[u8]>,
+    solution: Box<[u8]>,
+    cages: Box<[usize]>,
+}
+
+#[wasm_bindgen]
+impl Puzzle {
+    #[wasm_bindgen(getter)]
+    pub fn cells(&self) -> Box<[u8]> {
+        self.cells.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn solution(&self) -> Box<[u8]> {
+        self.solution.clone()
+    }
+
+    /// Killer cages as a flat list (see `generate_killer`), empty for other puzzles
+    #[wasm_bindgen(getter)]
+    pub fn cages(&self) -> Box<[usize]> {
+        self.cages.clone()
+    }
+}
+
+/// Generate a puzzle, keeping the solution to check entries against with `mark_wrong`
+#[wasm_bindgen]
+pub fn generate_puzzle(
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<Puzzle, JsError> {
+    console_error_panic_hook::set_once();
+
+    let mut sg = generate_solution(
+        world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default()),
+    )?;
+    let solution = sg.cells.clone();
+    punch_holes(&mut sg);
+
+    Ok(Puzzle {
+        cells: sg.cells,
+        solution,
+        cages: Box::new([]),
+    })
+}
+
+/// Largest number of cells in a killer cage (cells that are left over can still make a cage
+/// one larger)
+const MAX_CAGE_SIZE: usize = 4;
+/// Nodes of the search tree (per cell) to check that a killer puzzle is still unique
+/// without a given
+const KILLER_NODES_PER_CELL: usize = 100;
+
+/// Generate a killer puzzle: the solved world is split into cages with sum clues (which
+/// can span shared blocks and sudoku boundaries), and only the givens that are still needed
+/// next to them are kept
+///
+/// The cages are a flat list with the sum and the number of cells of every cage, followed
+/// by its cells (starting with the cell to write the sum in). Pass it back to the other
+/// functions to take the cages into account.
+#[wasm_bindgen]
+pub fn generate_killer(
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<Puzzle, JsError> {
+    console_error_panic_hook::set_once();
+
+    let sg = generate_solution(
+        world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default()),
+    )?;
+    let cages = sg.random_cages(MAX_CAGE_SIZE, random_int);
+    let mut sg = sg.with_cages(cages)?;
+    let solution = sg.cells.clone();
+    sg.remove_givens(KILLER_NODES_PER_CELL * sg.cells.len(), random_int);
+
+    Ok(Puzzle {
+        cages: Cage::to_flat(sg.cages()),
+        cells: sg.cells,
+        solution,
+    })
+}
+
+/// Fill an empty grid (of any topology) with a random solution
+///
+/// Fails if no solution is found, which can happen with variant rules (see [`Rules`]), or
+/// when filling the corner blocks of a classic world runs into a dead end.
+fn generate_solution(mut sg: SudokuGrid) -> Result<SudokuGrid, JsError> {
+    if sg.wrap().is_none() || sg.boxes() != BoxSize::CLASSIC || sg.rules() != Rules::default() {
+        return generate_shared_solution(sg);
+    }
+    let (n, m) = (sg.n, sg.m);
 
     // fill corners
     for x in 0..n {
         for y in 0..m {
+            if !sg.has_sudoku((x, y)) {
+                continue;
+            }
             // randomized depth-first solve block
-            let random_values = generate_random_sequence().collect::<Box<[u8]>>();
+            let random_values = generate_random_sequence(9).collect::<Box<[u8]>>();
             let backtracks = DfsBlock::new(&sg, (x, y), BOTTOM_LEFT_BLOCK, &random_values)
-                .next_solution(&mut sg)
-                .unwrap_or_else(|_| panic!("Could not solve\n{sg:?}"));
+                .next_solution(&mut sg)?;
             log!("Sudoku ({x}, {y}) bottom left: {backtracks} backtracks");
         }
 
         for y in 0..m {
+            if !sg.has_sudoku((x, y)) {
+                continue;
+            }
             // randomized depth-first solve block
-            let random_values = generate_random_sequence().collect::<Box<[u8]>>();
+            let random_values = generate_random_sequence(9).collect::<Box<[u8]>>();
             let mut backtracks = 0;
             let mut dfs = DfsBlock::new(&sg, (x, y), BOTTOM_RIGHT_BLOCK, &random_values);
             if dfs.next_solution(&mut sg).is_err() {
                 let mut other_dfs = DfsBlock::new(&sg, (x, y), TOP_RIGHT_BLOCK, &random_values);
                 other_dfs.reset(&mut sg);
-                other_dfs
-                    .next_solution(&mut sg)
-                    .expect("Could not solve other_dfs");
+                other_dfs.next_solution(&mut sg)?;
 
-                let other_sudoku = (x, (y + sg.m - 1) % sg.m);
+                // (there is no sudoku below on the edge of a bounded world)
                 let mut other_other_dfs =
-                    DfsBlock::new(&sg, other_sudoku, TOP_RIGHT_BLOCK, &random_values);
-                other_other_dfs.reset(&mut sg);
-                other_other_dfs
-                    .next_solution(&mut sg)
-                    .expect("Could not solve other_other_dfs");
+                    sg.shared_with((x, y), BOTTOM_RIGHT_BLOCK)
+                        .first()
+                        .map(|other_sudoku| {
+                            DfsBlock::new(&sg, *other_sudoku, TOP_RIGHT_BLOCK, &random_values)
+                        });
+                if let Some(other_other_dfs) = other_other_dfs.as_mut() {
+                    other_other_dfs.reset(&mut sg);
+                    other_other_dfs.next_solution(&mut sg)?;
+                }
 
                 dfs.reset(&mut sg);
                 // TODO: fix this - it seems to generate unsolvable sudokus about 50% of the time where the other_other_dfs is empty
                 while dfs.next_solution(&mut sg).is_err() {
                     if other_dfs.next_solution(&mut sg).is_err() {
                         other_dfs.reset(&mut sg);
-                        other_dfs
-                            .next_solution(&mut sg)
-                            .expect("Could not solve other_dfs");
-                        other_other_dfs
-                            .next_solution(&mut sg)
-                            .expect("Could not solve!");
+                        other_dfs.next_solution(&mut sg)?;
+                        if let Some(other_other_dfs) = other_other_dfs.as_mut() {
+                            other_other_dfs.next_solution(&mut sg)?;
+                        }
                     }
                     dfs.reset(&mut sg);
                     backtracks += 1;
@@ -94,27 +294,56 @@ pub fn generate(n: usize, m: usize, make_puzzle: bool) -> Box<[u8]> {
     // solve sudokus
     // (I assume it is always possible to solve them with any valid corner blocks)
     let mut solve_total_backtracks = 0;
-    for x in 0..n {
-        for y in 0..m {
-            let backtracks = sg
-                .depth_first_solve((x, y))
-                .expect("sudoku should be solvable");
-            log!("Sudoku ({x}, {y}) solve: {backtracks} backtracks");
-            solve_total_backtracks += backtracks;
-        }
+    for (x, y) in sg.sudoku_coords().collect::<Vec<_>>() {
+        let backtracks = sg.depth_first_solve((x, y))?;
+        log!("Sudoku ({x}, {y}) solve: {backtracks} backtracks");
+        solve_total_backtracks += backtracks;
     }
 
     log!("solve_total_backtracks: {solve_total_backtracks}");
 
-    if make_puzzle {
-        // punch holes
-        punch_holes(&mut sg);
+    Ok(sg)
+}
+
+/// Fill an empty grid whose shared blocks are not only corners (like the presets and edge
+/// tilings), whose blocks are not 3x3, or that has variant rules, with a random solution
+///
+/// Filling block by block relies on corner blocks: filling the shared bands of an edge
+/// tiling first with `DfsBlock` runs into dead ends for anything larger than 3x3 sudokus,
+/// as almost every block is shared. So this lets the solver fill the whole world at once,
+/// trying random values first. Now and then it gets stuck in a part of the search tree
+/// without solutions (especially with 16 values), so it starts over with other random
+/// values after a few times as many nodes as there are cells. Variant rules make the
+/// search harder, so every few attempts that number of nodes is doubled.
+fn generate_shared_solution(mut sg: SudokuGrid) -> Result<SudokuGrid, JsError> {
+    const ATTEMPTS: usize = 50;
+    const ATTEMPTS_PER_DOUBLING: usize = 10;
+    const FALLBACK_NODES_PER_CELL: usize = 1000;
+    let values = sg.boxes().values();
+    let solver = solver::Solver::new(&sg);
+    for attempt in 1..=ATTEMPTS {
+        let random_values = (0..sg.cells.len())
+            .map(|_| random_int(values) as u8 + 1)
+            .collect::<Box<[u8]>>();
+        let max_nodes = (4 * sg.cells.len()) << (attempt / ATTEMPTS_PER_DOUBLING);
+        if let Some(solution) = solver.clone().preferred_solution(&random_values, max_nodes) {
+            log!("solution found in attempt {attempt}");
+            sg.cells = solution;
+            return Ok(sg);
+        }
     }
 
-    sg.cells
+    log!("no random solution found in {ATTEMPTS} attempts");
+    // some worlds have no solution at all with variant rules, so don't search forever
+    let max_nodes = FALLBACK_NODES_PER_CELL * sg.cells.len();
+    sg.cells = solver
+        .preferred_solution(&vec![0; sg.cells.len()], max_nodes)
+        .ok_or_else(|| JsError::new("no solution found for this world and its rules"))?;
+    Ok(sg)
 }
 
 fn punch_holes(sg: &mut SudokuGrid) {
+    let values = sg.boxes().values() as u8;
     let mut indexes = (0..sg.cells.len()).collect::<Vec<usize>>();
     while !indexes.is_empty() {
         let i = indexes.swap_remove(random_int(indexes.len()));
@@ -131,13 +360,11 @@ fn punch_holes(sg: &mut SudokuGrid) {
             any_changed = false;
             for j in 0..sg.cells.len() {
                 if sg.cells[j] == 0 {
-                    let (sudoku_coords, other_coords) = sg.sudokus_at_index(j);
+                    let sudokus = sg.sudokus_at_index(j);
                     let mut success = 0;
-                    for n in 1..=9 {
+                    for n in 1..=values {
                         sg.cells[j] = n;
-                        if !sg.cell_is_problematic(sudoku_coords, j)
-                            && other_coords.is_none_or(|s| !sg.cell_is_problematic(s, j))
-                        {
+                        if sudokus.iter().all(|s| !sg.cell_is_problematic(*s, j)) {
                             if success == 0 {
                                 success = n;
                             } else {
@@ -146,7 +373,7 @@ fn punch_holes(sg: &mut SudokuGrid) {
                                 break;
                             }
                         }
-                        if n == 9 {
+                        if n == values {
                             sg.cells[j] = success;
                             any_changed = true;
                         }
@@ -170,21 +397,569 @@ fn punch_holes(sg: &mut SudokuGrid) {
 }
 
 #[wasm_bindgen]
-pub fn get_cell_index(n: usize, m: usize, sx: usize, sy: usize, scx: usize, scy: usize) -> usize {
-    let sg = SudokuGrid::new(n, m);
-    let block = scx / 3 + (scy / 3) * 3;
-    let start = sg.sudoku((sx, sy)).block_start[block];
-    start + scx % 3 + (scy % 3) * 3
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn get_cell_index(
+    n: usize,
+    m: usize,
+    sx: usize,
+    sy: usize,
+    scx: usize,
+    scy: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<usize, JsError> {
+    Ok(world_layout(n, m, wrap, preset, edges, boxes)?.index((sx, sy), scy, scx))
+}
+
+/// Get the index of the cell drawn at world coordinates (undefined for gray filler blocks)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn cell_index_at(
+    n: usize,
+    m: usize,
+    x: f64,
+    y: f64,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<Option<usize>, JsError> {
+    Ok(GridLayout::new(n, m, wrap, preset, edges, boxes)?.cell_at(x, y))
+}
+
+/// Get the world positions a cell is drawn at, as a flat list of x and y coordinates
+#[wasm_bindgen]
+pub fn world_positions(
+    n: usize,
+    m: usize,
+    i: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<Box<[i32]>, JsError> {
+    Ok(GridLayout::new(n, m, wrap, preset, edges, boxes)?.world_positions(i))
+}
+
+/// Conversions between cell indexes, sudoku positions, and world positions for a world of
+/// n by m sudokus (without allocating a grid)
+#[wasm_bindgen]
+pub struct GridLayout(Layout);
+
+#[wasm_bindgen]
+impl GridLayout {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        n: usize,
+        m: usize,
+        wrap: Option<Wrap>,
+        preset: Option<Preset>,
+        edges: Option<EdgeOverlap>,
+        boxes: Option<BoxSize>,
+    ) -> Result<GridLayout, JsError> {
+        Ok(GridLayout(world_layout(n, m, wrap, preset, edges, boxes)?))
+    }
+
+    /// Get the cell index at a row and column of sudoku (sx, sy)
+    pub fn index(&self, sx: usize, sy: usize, row: usize, column: usize) -> usize {
+        self.0.index((sx, sy), row, column)
+    }
+
+    /// Get every sudoku a cell is part of, as a flat list of (sx, sy, row, column)
+    pub fn positions(&self, i: usize) -> Box<[usize]> {
+        self.0
+            .positions(i)
+            .flat_map(|p| [p.sudoku.0, p.sudoku.1, p.row, p.column])
+            .collect()
+    }
+
+    /// Get the index of the cell drawn at world coordinates (undefined for gray filler
+    /// blocks)
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<usize> {
+        if !x.is_finite() || !y.is_finite() {
+            return None;
+        }
+        self.0.cell_at_world(x.floor() as isize, y.floor() as isize)
+    }
+
+    /// Get the cell next to cell i as seen in sudoku (sx, sy), as [index, sx, sy] of the
+    /// new cell and the sudoku to look at it in
+    pub fn neighbor(&self, i: usize, direction: Direction, sx: usize, sy: usize) -> Box<[usize]> {
+        let (j, (sx, sy)) = self.0.neighbor(i, direction, (sx, sy));
+        [j, sx, sy].into()
+    }
+
+    /// Get the world positions a cell is drawn at, as a flat list of x and y coordinates
+    pub fn world_positions(&self, i: usize) -> Box<[i32]> {
+        self.0
+            .world_positions(i)
+            .into_iter()
+            .flat_map(|(x, y)| [x as i32, y as i32])
+            .collect()
+    }
 }
 
 #[wasm_bindgen]
-pub fn mark_errors(cells: Box<[u8]>, n: usize, m: usize) -> Box<[u8]> {
-    let mut sg = SudokuGrid::new(n, m);
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn mark_errors(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<Box<[u8]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
     sg.cells = cells;
 
     sg.mark_errors();
 
-    sg.cells
+    Ok(sg.cells)
+}
+
+/// Mark user entries that differ from the solution as wrong (bit 64)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn mark_wrong(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    solution: &[u8],
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<Box<[u8]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?;
+    sg.cells = cells;
+
+    sg.mark_wrong(solution)?;
+
+    Ok(sg.cells)
+}
+
+/// Result of `check_dead_end`
+#[wasm_bindgen]
+pub struct DeadEnd {
+    /// Whether the user entries can still be completed to a solution (undefined if the
+    /// solver ran out of nodes before it could tell)
+    pub solvable: Option<bool>,
+    /// Whether no smaller set of entries to remove exists
+    pub minimal: bool,
+    remove: Box<[usize]>,
+}
+
+#[wasm_bindgen]
+impl DeadEnd {
+    /// User entries to remove to make the puzzle solvable again (empty if it is solvable,
+    /// or if the givens themselves have no solution)
+    #[wasm_bindgen(getter)]
+    pub fn remove(&self) -> Box<[usize]> {
+        self.remove.clone()
+    }
+}
+
+/// Check whether the user entries lead to a dead end, visiting at most `max_nodes` nodes of
+/// the search tree
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn check_dead_end(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    max_nodes: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<DeadEnd, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
+    sg.cells = cells;
+
+    let (solvable, minimal, remove) = match sg.check_dead_end(max_nodes) {
+        dead_end::Solvability::Solvable => (Some(true), true, Vec::new()),
+        dead_end::Solvability::DeadEnd { remove, minimal } => (Some(false), minimal, remove),
+        dead_end::Solvability::Unsolvable => (Some(false), false, Vec::new()),
+        dead_end::Solvability::Unknown => (None, false, Vec::new()),
+    };
+    Ok(DeadEnd {
+        solvable,
+        minimal,
+        remove: remove.into(),
+    })
+}
+
+/// Grid state decoded from a save or share string
+#[wasm_bindgen]
+pub struct State {
+    pub n: usize,
+    pub m: usize,
+    pub wrap: Wrap,
+    pub preset: Option<Preset>,
+    pub edges: Option<EdgeOverlap>,
+    pub boxes: BoxSize,
+    pub rules: Rules,
+    cells: Box<[u8]>,
+    cages: Box<[usize]>,
+}
+
+#[wasm_bindgen]
+impl State {
+    #[wasm_bindgen(getter)]
+    pub fn cells(&self) -> Box<[u8]> {
+        self.cells.clone()
+    }
+
+    /// Killer cages as a flat list (see `generate_killer`), empty for other puzzles
+    #[wasm_bindgen(getter)]
+    pub fn cages(&self) -> Box<[usize]> {
+        self.cages.clone()
+    }
+}
+
+impl From<SudokuGrid> for State {
+    fn from(sg: SudokuGrid) -> Self {
+        State {
+            n: sg.n,
+            m: sg.m,
+            wrap: sg.wrap().unwrap_or_default(),
+            preset: sg.preset(),
+            edges: sg.edge_overlap(),
+            boxes: sg.boxes(),
+            rules: sg.rules(),
+            cages: Cage::to_flat(sg.cages()),
+            cells: sg.cells,
+        }
+    }
+}
+
+/// Encode cells as a short URL-safe string (for save data and share links)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn encode_state(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<String, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
+    sg.cells = cells;
+
+    Ok(sg.encode_state())
+}
+
+/// Decode a string created by `encode_state`
+#[wasm_bindgen]
+pub fn decode_state(state: &str) -> Result<State, JsError> {
+    Ok(SudokuGrid::decode_state(state)?.into())
+}
+
+/// Export every sudoku as a standard 81-character string, prefixed with its coordinates
+#[wasm_bindgen]
+pub fn export_text(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<String, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?;
+    sg.cells = cells;
+
+    Ok(sg.to_text())
+}
+
+/// Import a world from the output of `export_text`
+#[wasm_bindgen]
+pub fn import_text(text: &str) -> Result<State, JsError> {
+    Ok(SudokuGrid::from_text(text)?.into())
+}
+
+/// Import a hand-made puzzle (a `size NxM` line followed by the `export_text` format),
+/// visiting at most `max_nodes` nodes of the search tree to check it
+///
+/// Fails if the givens conflict, if the puzzle does not have exactly one solution, or if
+/// the solver ran out of nodes before it could tell.
+#[wasm_bindgen]
+pub fn import_puzzle(description: &str, max_nodes: usize) -> Result<State, JsError> {
+    Ok(SudokuGrid::import_puzzle(description, max_nodes)?.into())
+}
+
+/// Draw a single sudoku as SVG (`notes` has a bitmap of pencil marks for every cell)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn sudoku_svg(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    sx: usize,
+    sy: usize,
+    notes: Option<Box<[u16]>>,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<String, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default());
+    sg.cells = cells;
+
+    Ok(sg.sudoku_svg((sx, sy), notes.as_deref()))
+}
+
+/// Draw the whole world as SVG, in the same layout as the WebGL view
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn world_svg(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    notes: Option<Box<[u16]>>,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<String, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default());
+    sg.cells = cells;
+
+    Ok(sg.world_svg(notes.as_deref()))
+}
+
+/// Create a printable booklet: one SVG page per sudoku, followed by an answer key (left
+/// out if the solver needs more than `max_nodes` nodes to solve the givens)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn booklet_svg(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    max_nodes: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+) -> Result<Vec<String>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?.with_rules(rules.unwrap_or_default());
+    sg.cells = cells;
+
+    Ok(sg.booklet_svg(max_nodes))
+}
+
+/// Get all cells sharing a row, column, block, extra region, or cage with cell i, in any
+/// sudoku it is part of, and the cells a chess move away from it
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn peers(
+    n: usize,
+    m: usize,
+    i: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<Box<[usize]>, JsError> {
+    Ok(world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?
+        .peers(i)
+        .into())
+}
+
+/// Get all cells in the world with the same value as cell i (empty if it has no value)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn same_value_cells(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    i: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+) -> Result<Box<[usize]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?;
+    sg.cells = cells;
+
+    Ok(sg.cells_with_value(sg.value(i)).into())
+}
+
+/// Get all empty cells in the world where the value of cell i could still be entered
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn candidate_cells(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    i: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<Box<[usize]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
+    sg.cells = cells;
+
+    Ok(sg.candidate_cells(sg.value(i)).into())
+}
+
+/// Get the cells clashing with cell i, as a flat list of (index, region kind, sx, sy) with
+/// the region and sudoku of the clash
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn conflicts(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    i: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<Box<[usize]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
+    sg.cells = cells;
+
+    Ok(sg
+        .conflicts(i)
+        .into_iter()
+        .flat_map(|c| [c.index, c.region as usize, c.sudoku.0, c.sudoku.1])
+        .collect())
+}
+
+/// Pack the cells, notes, and highlights for the selected cell into an RGBA texture
+/// (see the `render` module for the layout)
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+pub fn render_buffer(
+    cells: Box<[u8]>,
+    n: usize,
+    m: usize,
+    notes: Option<Box<[u16]>>,
+    selected: Option<usize>,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    rules: Option<Rules>,
+    cages: Option<Box<[usize]>>,
+) -> Result<Box<[u8]>, JsError> {
+    let mut sg = world(n, m, wrap, preset, edges, boxes)?
+        .with_rules(rules.unwrap_or_default())
+        .with_cages(Cage::from_flat(&cages.unwrap_or_default())?)?;
+    sg.cells = cells;
+
+    let buffer = render::RenderBuffer::new(&sg).selected(selected);
+    Ok(match notes.as_deref() {
+        Some(notes) => buffer.notes(notes).build(),
+        None => buffer.build(),
+    })
+}
+
+/// Pack the killer cages (sums and borders) into a second RGBA texture (see the `render`
+/// module for the layout)
+#[wasm_bindgen]
+pub fn cage_render_buffer(
+    n: usize,
+    m: usize,
+    wrap: Option<Wrap>,
+    preset: Option<Preset>,
+    edges: Option<EdgeOverlap>,
+    boxes: Option<BoxSize>,
+    cages: Box<[usize]>,
+) -> Result<Box<[u8]>, JsError> {
+    let sg = world(n, m, wrap, preset, edges, boxes)?.with_cages(Cage::from_flat(&cages)?)?;
+    Ok(render::cage_buffer(&sg))
+}
+
+/// Render buffer that remembers which texture rows changed since they were uploaded
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Renderer(render::DirtyTracker);
+
+#[wasm_bindgen]
+impl Renderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render the grid again, marking the rows that changed
+    #[allow(clippy::too_many_arguments)] // plain arguments are easiest to pass from JavaScript
+    pub fn update(
+        &mut self,
+        cells: Box<[u8]>,
+        n: usize,
+        m: usize,
+        notes: Option<Box<[u16]>>,
+        selected: Option<usize>,
+        wrap: Option<Wrap>,
+        preset: Option<Preset>,
+        edges: Option<EdgeOverlap>,
+        boxes: Option<BoxSize>,
+        rules: Option<Rules>,
+        cages: Option<Box<[usize]>>,
+    ) -> Result<(), JsError> {
+        let buffer = render_buffer(
+            cells, n, m, notes, selected, wrap, preset, edges, boxes, rules, cages,
+        )?;
+        self.0.update(buffer, boxes.unwrap_or_default());
+        Ok(())
+    }
+
+    /// The full render buffer
+    pub fn buffer(&self) -> Box<[u8]> {
+        self.0.buffer().into()
+    }
+
+    /// Get the rows changed since the last call as a flat list of [start, end) pairs, to
+    /// upload with `texSubImage2D`
+    pub fn take_dirty_ranges(&mut self) -> Box<[usize]> {
+        self.0
+            .take_dirty_ranges()
+            .into_iter()
+            .flat_map(|r| [r.start, r.end])
+            .collect()
+    }
+
+    /// Get the bytes of the render buffer for rows [start, end) (the last row can be partial,
+    /// and rows outside the buffer are left out)
+    pub fn rows(&self, start: usize, end: usize) -> Box<[u8]> {
+        self.0.rows(start, end).into()
+    }
 }
 
 #[cfg(test)]
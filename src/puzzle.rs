@@ -0,0 +1,194 @@
+//! Import of hand-made puzzles
+//!
+//! A puzzle description starts with a `size NxM` line, followed by the givens of every
+//! sudoku in the text format of [`SudokuGrid::from_text`]:
+//!
+//! ```text
+//! size 2x1
+//! 0,0 ..3.1..8.5...
+//! 1,0 .7....2.6....
+//! ```
+//!
+//! (with 81 cells after the coordinates of each sudoku). Sudokus with blocks of another
+//! size have a `boxes WxH` line after the size line.
+
+use crate::sudokus::SudokuGrid;
+use crate::text::TextError;
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    /// The `size NxM` line is missing or malformed
+    InvalidSize,
+    /// The size line does not match the sudokus that are described
+    SizeMismatch,
+    /// The givens could not be parsed
+    Text(TextError),
+    /// These givens clash with another given in a row, column, or block
+    Conflicts(Vec<usize>),
+    /// There is no way to complete the puzzle
+    Unsolvable,
+    /// There are multiple ways to complete the puzzle; these cells differ between them
+    Ambiguous(Vec<usize>),
+    /// The solver ran out of nodes before it could tell whether the puzzle has exactly one
+    /// solution
+    Unknown,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImportError::InvalidSize => write!(f, "expected `size NxM` on the first line"),
+            ImportError::SizeMismatch => write!(f, "sudokus do not match the given size"),
+            ImportError::Text(e) => write!(f, "{e}"),
+            ImportError::Conflicts(cells) => write!(f, "conflicting givens at {cells:?}"),
+            ImportError::Unsolvable => write!(f, "puzzle has no solution"),
+            ImportError::Ambiguous(cells) => {
+                write!(f, "puzzle has multiple solutions, differing at {cells:?}")
+            }
+            ImportError::Unknown => write!(f, "puzzle is too hard to check for a solution"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<TextError> for ImportError {
+    fn from(e: TextError) -> Self {
+        ImportError::Text(e)
+    }
+}
+
+fn parse_size(line: &str) -> Option<(usize, usize)> {
+    let (n, m) = line.trim().strip_prefix("size ")?.split_once('x')?;
+    Some((n.trim().parse().ok()?, m.trim().parse().ok()?))
+}
+
+impl SudokuGrid {
+    /// Import a hand-made puzzle, checking that its givens are consistent and that it has
+    /// exactly one solution, visiting at most `max_nodes` nodes of the search tree
+    ///
+    /// All non-zero cells of the result are givens (so without the user-entered flag).
+    pub fn import_puzzle(description: &str, max_nodes: usize) -> Result<SudokuGrid, ImportError> {
+        let mut lines = description
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+        let (n, m) = lines
+            .next()
+            .and_then(parse_size)
+            .ok_or(ImportError::InvalidSize)?;
+
+        let mut sg = SudokuGrid::from_text(&lines.collect::<Vec<_>>().join("\n"))?;
+        if (sg.n, sg.m) != (n, m) {
+            return Err(ImportError::SizeMismatch);
+        }
+
+        sg.mark_errors();
+        let error = sg.boxes().error_flag();
+        let conflicts = (0..sg.cells.len())
+            .filter(|i| sg.cells[*i] & error > 0)
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            return Err(ImportError::Conflicts(conflicts));
+        }
+
+        let solutions = sg
+            .solutions_within(2, max_nodes)
+            .ok_or(ImportError::Unknown)?;
+        match solutions.as_slice() {
+            [] => Err(ImportError::Unsolvable),
+            [_] => Ok(sg),
+            [a, b, ..] => Err(ImportError::Ambiguous(
+                (0..a.len()).filter(|i| a[*i] != b[*i]).collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODES: usize = 100_000;
+
+    fn solved_grid(n: usize, m: usize) -> SudokuGrid {
+        let mut sg = SudokuGrid::new(n, m);
+        sg.cells = sg.solutions(1).remove(0);
+        sg
+    }
+
+    #[test]
+    fn import_unique_puzzle() {
+        let mut sg = solved_grid(2, 1);
+        let solution = sg.cells.clone();
+        for i in [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+            sg.cells[i] = 0;
+        }
+
+        let description = format!("# hand-made\nsize 2x1\n{}", sg.to_text());
+        let imported = SudokuGrid::import_puzzle(&description, NODES).unwrap();
+        assert_eq!(imported.cells, sg.cells);
+        assert!(imported.cells.iter().all(|c| c & 16 == 0));
+        assert_eq!(imported.solutions(2), vec![solution]);
+    }
+
+    #[test]
+    fn import_invalid_puzzle() {
+        let sg = solved_grid(2, 1);
+        let text = sg.to_text();
+
+        assert_eq!(
+            SudokuGrid::import_puzzle(&text, NODES).err(),
+            Some(ImportError::InvalidSize)
+        );
+        assert_eq!(
+            SudokuGrid::import_puzzle(&format!("size 1x1\n{text}"), NODES).err(),
+            Some(ImportError::SizeMismatch)
+        );
+        assert!(matches!(
+            SudokuGrid::import_puzzle("size 1x1\n0,0 12", NODES),
+            Err(ImportError::Text(TextError::WrongLength(_)))
+        ));
+    }
+
+    #[test]
+    fn import_conflicting_puzzle() {
+        let mut sg = SudokuGrid::new(1, 1);
+        sg.cells[0] = 3;
+        sg.cells[8] = 3;
+        let description = format!("size 1x1\n{}", sg.to_text());
+        assert_eq!(
+            SudokuGrid::import_puzzle(&description, NODES).err(),
+            Some(ImportError::Conflicts(vec![0, 8]))
+        );
+    }
+
+    #[test]
+    fn import_ambiguous_puzzle() {
+        let mut sg = solved_grid(1, 1);
+        let s = sg.sudoku((0, 0)).clone();
+        let cleared = sg
+            .block(&s, crate::sudokus::MIDDLE_CENTER_BLOCK)
+            .indexes()
+            .chain(sg.block(&s, crate::sudokus::MIDDLE_LEFT_BLOCK).indexes())
+            .chain(sg.block(&s, crate::sudokus::MIDDLE_RIGHT_BLOCK).indexes())
+            .collect::<Vec<_>>();
+        // rows of the middle band can be swapped
+        for i in cleared.iter() {
+            sg.cells[*i] = 0;
+        }
+
+        let description = format!("size 1x1\n{}", sg.to_text());
+        let Err(ImportError::Ambiguous(cells)) = SudokuGrid::import_puzzle(&description, NODES)
+        else {
+            panic!("expected puzzle to be ambiguous");
+        };
+        assert!(!cells.is_empty());
+        assert!(cells.iter().all(|i| cleared.contains(i)));
+
+        // telling that takes more than a few nodes
+        assert_eq!(
+            SudokuGrid::import_puzzle(&description, 10).err(),
+            Some(ImportError::Unknown)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Render buffer for the WebGL view
+//!
+//! The buffer holds one RGBA texel per cell, in the same order as [`SudokuGrid::cells`], so
+//! it can be uploaded as a [`row_texels`] by `n * m` texture: every row of the texture is
+//! one sudoku (`x + y * n`), and every column is a cell of the blocks stored by that sudoku
+//! (7 blocks of 9 cells for 3x3 blocks). A bounded world has `n + m` unshared corner blocks
+//! after those, with a texel for every cell of the block (see
+//! [`Layout::len`](crate::layout::Layout::len)). Other topologies (like the presets) can
+//! end in a partial row.
+//!
+//! - R: the cell as stored in the grid: value, user entered, error, and wrong flags (16, 32,
+//!   and 64 for up to 15 values, see [`BoxSize::value_bits`])
+//! - G: notes for the values 1 to 8 (bit `v - 1` for value `v`)
+//! - B: note for value 9 ([`NOTE_9`]), [`WINDOW`] for the shaded cells of a Windoku, and
+//!   highlight flags for the selected cell
+//! - A: 255
+//!
+//! Killer cages go in a second texture of the same size ([`cage_buffer`]), which only
+//! changes when a new puzzle is started:
+//!
+//! - R and G: the sum of the cage as a 16-bit number (low byte in R), in the first cell of
+//!   the cage only
+//! - B: [`IN_CAGE`] for cells in a cage, and a flag for every side of the cell that is the
+//!   border of its cage ([`BORDER_UP`], [`BORDER_DOWN`], [`BORDER_LEFT`], [`BORDER_RIGHT`])
+//! - A: 255
+
+use std::ops::Range;
+
+use crate::layout::Direction;
+use crate::sudokus::{BoxSize, SudokuGrid};
+
+pub const TEXEL_BYTES: usize = 4;
+
+/// Texels per row of the texture: the cells of the blocks stored by one sudoku (see
+/// [`BoxSize::block_memory_order`])
+pub fn row_texels(boxes: BoxSize) -> usize {
+    boxes.block_memory_order().count() * boxes.values()
+}
+
+// flags in the blue channel
+pub const NOTE_9: u8 = 1;
+/// This is the selected cell
+pub const SELECTED: u8 = 2;
+/// Shares a row, column, or block with the selected cell
+pub const PEER: u8 = 4;
+/// Contains the same value as the selected cell
+pub const SAME_VALUE: u8 = 8;
+/// Has a note for the value of the selected cell
+pub const SAME_VALUE_NOTE: u8 = 16;
+/// Is part of a sudoku the selected cell is part of
+pub const SELECTED_SUDOKU: u8 = 32;
+/// Is part of a window (see [`Rules::windows`](crate::rules::Rules::windows)), set
+/// whether a cell is selected or not
+pub const WINDOW: u8 = 64;
+
+// flags in the blue channel of the cage texture
+pub const BORDER_UP: u8 = 1;
+pub const BORDER_DOWN: u8 = 2;
+pub const BORDER_LEFT: u8 = 4;
+pub const BORDER_RIGHT: u8 = 8;
+pub const IN_CAGE: u8 = 16;
+
+pub struct RenderBuffer<'a> {
+    sg: &'a SudokuGrid,
+    notes: Option<&'a [u16]>,
+    selected: Option<usize>,
+}
+
+impl<'a> RenderBuffer<'a> {
+    pub fn new(sg: &'a SudokuGrid) -> Self {
+        RenderBuffer {
+            sg,
+            notes: None,
+            selected: None,
+        }
+    }
+
+    /// Add notes, as a bitmap for every cell (bit `v` set for value `v`)
+    pub fn notes(mut self, notes: &'a [u16]) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+
+    /// Highlight the cells related to the selected cell
+    pub fn selected(mut self, selected: Option<usize>) -> Self {
+        self.selected = selected;
+        self
+    }
+
+    pub fn build(&self) -> Box<[u8]> {
+        let mut buffer = vec![0; self.sg.cells.len() * TEXEL_BYTES];
+        for (i, texel) in buffer.chunks_exact_mut(TEXEL_BYTES).enumerate() {
+            let notes = self.notes.and_then(|n| n.get(i)).copied().unwrap_or(0);
+            texel[0] = self.sg.cells[i];
+            texel[1] = (notes >> 1) as u8;
+            texel[2] = if notes & (1 << 9) > 0 { NOTE_9 } else { 0 };
+            if self.sg.in_window(i) {
+                texel[2] |= WINDOW;
+            }
+            texel[3] = 255;
+        }
+        let flags = |buffer: &mut [u8], i: usize, flag: u8| buffer[i * TEXEL_BYTES + 2] |= flag;
+
+        let Some(selected) = self.selected else {
+            return buffer.into();
+        };
+
+        for coords in self.sg.sudokus_at_index(selected) {
+            for i in self.sg.sudoku(coords).indexes() {
+                flags(&mut buffer, i, SELECTED_SUDOKU);
+            }
+        }
+        for i in self.sg.peers(selected) {
+            flags(&mut buffer, i, PEER);
+        }
+        flags(&mut buffer, selected, SELECTED);
+
+        let value = self.sg.value(selected);
+        for i in self.sg.cells_with_value(value) {
+            flags(&mut buffer, i, SAME_VALUE);
+        }
+        if let (Some(notes), true) = (self.notes, value > 0) {
+            for (i, notes) in notes.iter().enumerate().take(self.sg.cells.len()) {
+                if self.sg.value(i) == 0 && u32::from(*notes) & (1 << value) > 0 {
+                    flags(&mut buffer, i, SAME_VALUE_NOTE);
+                }
+            }
+        }
+
+        buffer.into()
+    }
+}
+
+/// Pack the killer cages of the grid into an RGBA texture (see the module documentation)
+///
+/// Borders are taken from where the cell is drawn in its first sudoku, so a cage that
+/// goes on in a neighbouring sudoku has no border there.
+pub fn cage_buffer(sg: &SudokuGrid) -> Box<[u8]> {
+    let layout = sg.layout();
+    let mut buffer = vec![0; sg.cells.len() * TEXEL_BYTES];
+    for (i, texel) in buffer.chunks_exact_mut(TEXEL_BYTES).enumerate() {
+        texel[3] = 255;
+        let Some(cage) = sg.cage_for(i) else {
+            continue;
+        };
+        if cage.cells[0] == i {
+            texel[0] = cage.sum as u8;
+            texel[1] = (cage.sum >> 8) as u8;
+        }
+        texel[2] = IN_CAGE;
+        let owner = layout
+            .positions(i)
+            .next()
+            .expect("every cell is part of a sudoku")
+            .sudoku;
+        for (direction, border) in [
+            (Direction::Up, BORDER_UP),
+            (Direction::Down, BORDER_DOWN),
+            (Direction::Left, BORDER_LEFT),
+            (Direction::Right, BORDER_RIGHT),
+        ] {
+            let (j, _) = layout.neighbor(i, direction, owner);
+            if j == i || !cage.cells.contains(&j) {
+                texel[2] |= border;
+            }
+        }
+    }
+    buffer.into()
+}
+
+/// Keeps the last render buffer, and which rows of it changed since they were uploaded
+#[derive(Default)]
+pub struct DirtyTracker {
+    buffer: Box<[u8]>,
+    /// Bytes per row of the texture (see [`row_texels`])
+    row_bytes: usize,
+    dirty: Vec<bool>,
+}
+
+impl DirtyTracker {
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Get the bytes of rows [start, end) (the last row can be partial), empty for rows
+    /// outside the buffer
+    pub fn rows(&self, start: usize, end: usize) -> &[u8] {
+        let end = self.buffer.len().min(end.saturating_mul(self.row_bytes));
+        let start = end.min(start.saturating_mul(self.row_bytes));
+        &self.buffer[start..end]
+    }
+
+    /// Replace the buffer of a grid with blocks of a size, marking every row that differs as
+    /// dirty
+    pub fn update(&mut self, buffer: Box<[u8]>, boxes: BoxSize) {
+        let row_bytes = row_texels(boxes) * TEXEL_BYTES;
+        if buffer.len() != self.buffer.len() || row_bytes != self.row_bytes {
+            // the world was resized, so everything has to be uploaded again
+            self.row_bytes = row_bytes;
+            self.dirty = vec![true; buffer.len().div_ceil(row_bytes)];
+        } else {
+            let rows = buffer.chunks(row_bytes).zip(self.buffer.chunks(row_bytes));
+            for (dirty, (new, old)) in self.dirty.iter_mut().zip(rows) {
+                *dirty |= new != old;
+            }
+        }
+        self.buffer = buffer;
+    }
+
+    /// Get the ranges of rows that changed since the last call, merging adjacent rows
+    pub fn take_dirty_ranges(&mut self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (row, dirty) in self.dirty.iter_mut().enumerate() {
+            if !std::mem::take(dirty) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end == row => range.end += 1,
+                _ => ranges.push(row..row + 1),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cages::Cage;
+    use crate::rules::Rules;
+    use crate::topology::{GridTopology, Wrap};
+
+    #[test]
+    fn render_cells_and_notes() {
+        let mut sg = SudokuGrid::new(2, 1);
+        sg.cells[0] = 5;
+        sg.cells[1] = 3 + 16 + 32;
+        let mut notes = vec![0; sg.cells.len()];
+        notes[2] = (1 << 1) | (1 << 8) | (1 << 9);
+
+        let buffer = RenderBuffer::new(&sg).notes(&notes).build();
+        assert_eq!(buffer.len(), 2 * 7 * 9 * TEXEL_BYTES);
+        assert_eq!(
+            &buffer[..12],
+            [5, 0, 0, 255, 51, 0, 0, 255, 0, 0b1000_0001, NOTE_9, 255]
+        );
+    }
+
+    #[test]
+    fn render_selection() {
+        let mut sg = SudokuGrid::new(2, 1);
+        let layout = sg.layout();
+        let selected = layout.index((0, 0), 4, 4);
+        let same_row = layout.index((0, 0), 4, 0);
+        let other_sudoku = layout.index((1, 0), 4, 4);
+        let noted = layout.index((1, 0), 0, 0);
+        sg.cells[selected] = 7;
+        sg.cells[other_sudoku] = 7 + 16;
+        let mut notes = vec![0; sg.cells.len()];
+        notes[noted] = 1 << 7;
+
+        let buffer = RenderBuffer::new(&sg)
+            .notes(&notes)
+            .selected(Some(selected))
+            .build();
+        let flags = |i: usize| buffer[i * TEXEL_BYTES + 2];
+        assert_eq!(flags(selected), SELECTED | SELECTED_SUDOKU | SAME_VALUE);
+        assert_eq!(flags(same_row), PEER | SELECTED_SUDOKU);
+        assert_eq!(flags(other_sudoku), SAME_VALUE);
+        assert_eq!(flags(noted), SAME_VALUE_NOTE);
+
+        // without a selection, there are no highlights
+        let buffer = RenderBuffer::new(&sg).notes(&notes).build();
+        assert!(buffer.chunks(TEXEL_BYTES).all(|t| t[2] == 0));
+    }
+
+    #[test]
+    fn render_windows() {
+        let sg = SudokuGrid::new(2, 1).with_rules(Rules {
+            windows: true,
+            ..Rules::default()
+        });
+        let layout = sg.layout();
+        let buffer = RenderBuffer::new(&sg).build();
+        let flags = |i: usize| buffer[i * TEXEL_BYTES + 2];
+        assert_eq!(flags(layout.index((1, 0), 2, 3)), WINDOW);
+        assert_eq!(flags(layout.index((1, 0), 4, 4)), 0);
+        let windows = buffer
+            .chunks(TEXEL_BYTES)
+            .filter(|t| t[2] == WINDOW)
+            .count();
+        // neighbouring windows meet in one cell of every shared block
+        assert_eq!(windows, 2 * 4 * 9 - 4);
+    }
+
+    #[test]
+    fn render_cages() {
+        let sg = SudokuGrid::with_wrap(2, 1, Wrap::Bounded);
+        let layout = sg.layout();
+        let (left, right) = (layout.index((0, 0), 0, 0), layout.index((0, 0), 0, 1));
+        let sg = sg
+            .with_cages(vec![Cage {
+                sum: 300,
+                cells: [left, right].into(),
+            }])
+            .unwrap();
+        let buffer = cage_buffer(&sg);
+        let texel = |i: usize| &buffer[i * TEXEL_BYTES..(i + 1) * TEXEL_BYTES];
+        // the top left corner of a bounded world has borders on the outside too
+        assert_eq!(
+            texel(left),
+            [44, 1, IN_CAGE | BORDER_UP | BORDER_DOWN | BORDER_LEFT, 255]
+        );
+        assert_eq!(
+            texel(right),
+            [0, 0, IN_CAGE | BORDER_UP | BORDER_DOWN | BORDER_RIGHT, 255]
+        );
+        assert_eq!(texel(layout.index((0, 0), 4, 4)), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn render_other_box_sizes() {
+        assert_eq!(row_texels(BoxSize::CLASSIC), 7 * 9);
+        let topology = GridTopology::new(2, 1, Wrap::Torus)
+            .with_boxes(BoxSize::new(2, 2).unwrap())
+            .unwrap();
+        let sg = SudokuGrid::with_topology(topology);
+        assert_eq!(row_texels(sg.boxes()), 2 * 4);
+        let mut tracker = DirtyTracker::default();
+        tracker.update(RenderBuffer::new(&sg).build(), sg.boxes());
+        assert_eq!(tracker.take_dirty_ranges(), vec![0..2]);
+
+        // 16 values take a fifth bit, so the user flag is 32
+        let topology = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(BoxSize::new(4, 4).unwrap())
+            .unwrap();
+        let mut sg = SudokuGrid::with_topology(topology);
+        let layout = sg.layout();
+        let (a, b) = (layout.index((0, 0), 0, 0), layout.index((0, 0), 15, 15));
+        sg.cells[a] = 16;
+        sg.cells[b] = 16 + sg.boxes().user_flag();
+        let buffer = RenderBuffer::new(&sg).selected(Some(a)).build();
+        assert_eq!(buffer[b * TEXEL_BYTES + 2], SAME_VALUE | SELECTED_SUDOKU);
+    }
+
+    #[test]
+    fn dirty_ranges() {
+        let mut sg = SudokuGrid::new(4, 1);
+        let mut tracker = DirtyTracker::default();
+        tracker.update(RenderBuffer::new(&sg).build(), sg.boxes());
+        assert_eq!(tracker.take_dirty_ranges(), vec![0..4]);
+        assert_eq!(tracker.take_dirty_ranges(), []);
+
+        // unchanged rows are not uploaded again
+        tracker.update(RenderBuffer::new(&sg).build(), sg.boxes());
+        assert_eq!(tracker.take_dirty_ranges(), []);
+
+        let layout = sg.layout();
+        sg.cells[layout.index((0, 0), 4, 4)] = 1;
+        tracker.update(RenderBuffer::new(&sg).build(), sg.boxes());
+        sg.cells[layout.index((2, 0), 4, 4)] = 2;
+        sg.cells[layout.index((3, 0), 4, 4)] = 3;
+        tracker.update(RenderBuffer::new(&sg).build(), sg.boxes());
+        assert_eq!(tracker.take_dirty_ranges(), [0..1, 2..4]);
+        assert_eq!(tracker.buffer(), &RenderBuffer::new(&sg).build()[..]);
+
+        let row_bytes = row_texels(sg.boxes()) * TEXEL_BYTES;
+        assert_eq!(
+            tracker.rows(1, 3),
+            &tracker.buffer()[row_bytes..3 * row_bytes]
+        );
+        assert_eq!(tracker.rows(3, 10), &tracker.buffer()[3 * row_bytes..]);
+        assert!(tracker.rows(3, 1).is_empty());
+        assert!(tracker.rows(5, 6).is_empty());
+        assert!(tracker.rows(usize::MAX, usize::MAX).is_empty());
+    }
+}
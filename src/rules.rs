@@ -0,0 +1,528 @@
+//! Variant rules that add regions to every sudoku of a world
+//!
+//! Besides its rows, columns, and blocks, a sudoku can have extra regions that also hold
+//! every value once. They are listed once per grid by [`SudokuGrid::extra_regions`] as plain
+//! lists of indexes, so everything that checks or solves regions (`is_solved`,
+//! `cell_is_problematic`, `peers`, `conflicts`, `solve_trivial_regions`, and through those
+//! the generator and the solvers) treats them like any other region.
+//!
+//! Chess rules are not regions: they forbid the same value in cells a knight's or king's
+//! move apart. The moves are made in the world (see
+//! [`Layout::cell_at_world`](crate::layout::Layout::cell_at_world)), so they go across the
+//! edges of sudokus and around a wrapping world. They are looked up once per grid, and
+//! added to `peers` (and so to the solvers), `cell_is_problematic`, and `conflicts`.
+
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::sudokus::{RegionKind, Sudoku, SudokuGrid};
+
+/// Regions the rules add to every sudoku, ordered like the sudokus of the grid
+pub type ExtraRegions = Rc<[Box<[(RegionKind, Box<[usize]>)]>]>;
+
+/// Cells a chess move away from every cell, with the rule of the move
+pub type ChessPeers = Rc<[Box<[(RegionKind, usize)]>]>;
+
+/// World offsets of a knight's moves
+const KNIGHT_MOVES: [(isize, isize); 8] = [
+    (1, -2),
+    (2, -1),
+    (2, 1),
+    (1, 2),
+    (-1, 2),
+    (-2, 1),
+    (-2, -1),
+    (-1, -2),
+];
+
+/// World offsets of a king's moves
+const KING_MOVES: [(isize, isize); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// Variant rules that hold in every sudoku of a world
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rules {
+    /// Both main diagonals of every sudoku hold every value once (X-sudoku)
+    ///
+    /// In a world, diagonals run through the corner blocks, so they also constrain the
+    /// sudokus sharing those blocks. Not every world has a solution then: worlds that wrap
+    /// around after a single sudoku never do, and tori with an odd size often don't.
+    pub diagonals: bool,
+    /// Four extra 3x3 windows in every sudoku hold every value once (Windoku)
+    ///
+    /// The windows are blocks moved one cell away from the edges of the sudoku, with one
+    /// cell between them, so sudokus with other block sizes have as many windows as fit
+    /// (one for 2x2 blocks, nine for 4x4 blocks).
+    pub windows: bool,
+    /// Cells a knight's move apart never hold the same value (anti-knight)
+    ///
+    /// Like the king's moves, knight's moves are made in the world, so they reach into the
+    /// neighbouring sudokus. Bounded worlds can be filled, but in wrapping worlds a
+    /// solution is hard to find, if there is one at all.
+    pub anti_knight: bool,
+    /// Cells a king's move apart never hold the same value (anti-king), which only adds
+    /// the diagonal neighbours in other blocks
+    pub anti_king: bool,
+}
+
+#[wasm_bindgen]
+impl Rules {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Rules {
+    /// Rules as a byte for the save format (bit 0 diagonals, bit 1 windows, bit 2
+    /// anti-knight, bit 3 anti-king)
+    pub fn to_bits(self) -> u8 {
+        self.diagonals as u8
+            | (self.windows as u8) << 1
+            | (self.anti_knight as u8) << 2
+            | (self.anti_king as u8) << 3
+    }
+
+    /// Rules from a byte created by [`Rules::to_bits`] (`None` if it has unknown bits)
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits > 15 {
+            return None;
+        }
+        Some(Rules {
+            diagonals: bits & 1 > 0,
+            windows: bits & 2 > 0,
+            anti_knight: bits & 4 > 0,
+            anti_king: bits & 8 > 0,
+        })
+    }
+}
+
+impl SudokuGrid {
+    /// Find the regions the rules add to every sudoku, for [`SudokuGrid::extra_regions`]
+    pub(crate) fn sudoku_regions(&self) -> ExtraRegions {
+        let rules = self.rules();
+        if !rules.diagonals && !rules.windows {
+            return Rc::new([]); // chess rules have no regions
+        }
+        self.sudoku_coords()
+            .map(|coords| self.find_extra_regions(self.sudoku(coords)).into())
+            .collect()
+    }
+
+    /// Find the cells that are part of a window of any sudoku, for
+    /// [`SudokuGrid::in_window`]
+    pub(crate) fn window_cells(&self) -> Rc<[bool]> {
+        if !self.rules().windows {
+            return Rc::new([]);
+        }
+        let mut cells = vec![false; self.cells.len()];
+        for coords in self.sudoku_coords() {
+            for (kind, indexes) in self.extra_regions(self.sudoku(coords)) {
+                if *kind == RegionKind::Window {
+                    for i in indexes.iter() {
+                        cells[*i] = true;
+                    }
+                }
+            }
+        }
+        cells.into()
+    }
+
+    fn find_extra_regions(&self, sudoku: &Sudoku) -> Vec<(RegionKind, Box<[usize]>)> {
+        let rules = self.rules();
+        let mut regions = Vec::new();
+        let boxes = self.boxes();
+        let values = boxes.values();
+        let layout = self.layout();
+        let coords = sudoku.coords();
+        if rules.diagonals {
+            regions.push((
+                RegionKind::Diagonal,
+                (0..values).map(|k| layout.index(coords, k, k)).collect(),
+            ));
+            regions.push((
+                RegionKind::Diagonal,
+                (0..values)
+                    .map(|k| layout.index(coords, k, values - 1 - k))
+                    .collect(),
+            ));
+        }
+        if rules.windows {
+            // first row or column of every window, leaving a cell free at both edges
+            let starts = |size: usize| {
+                (1..)
+                    .step_by(size + 1)
+                    .take_while(move |start| start + size < values)
+            };
+            for top in starts(boxes.height) {
+                for left in starts(boxes.width) {
+                    let cell = |o: usize| {
+                        layout.index(coords, top + o / boxes.width, left + o % boxes.width)
+                    };
+                    regions.push((RegionKind::Window, (0..values).map(cell).collect()));
+                }
+            }
+        }
+        regions
+    }
+
+    /// Find the cells a chess move away from every cell, for the chess rules (see
+    /// [`SudokuGrid::chess_peers`])
+    pub(crate) fn chess_moves(&self) -> ChessPeers {
+        let rules = self.rules();
+        let moves = [
+            (RegionKind::Knight, rules.anti_knight, KNIGHT_MOVES),
+            (RegionKind::King, rules.anti_king, KING_MOVES),
+        ];
+        if moves.iter().all(|(_, enabled, _)| !enabled) {
+            return Rc::new([]);
+        }
+
+        let layout = self.layout();
+        (0..self.cells.len())
+            .map(|i| {
+                let mut peers = Vec::new();
+                // a cell in a shared corner block is part of two sudokus
+                for p in layout.positions(i) {
+                    for (kind, _, offsets) in moves.iter().filter(|(_, enabled, _)| *enabled) {
+                        for offset in offsets {
+                            match layout.cell_at_offset(p, *offset) {
+                                Some(j) if j != i => peers.push((*kind, j)),
+                                _ => {} // gray block, edge of the world, or back to itself
+                            }
+                        }
+                    }
+                }
+                peers.sort_unstable_by_key(|(kind, j)| (*j, *kind as u8));
+                peers.dedup();
+                peers.into()
+            })
+            .collect()
+    }
+
+    /// Check if the value in the cell at index i is also in a cell a knight's or king's move
+    /// away, when the chess rules forbid it
+    pub fn chess_is_problematic(&self, i: usize) -> bool {
+        let value = self.value(i);
+        value > 0
+            && self
+                .chess_peers(i)
+                .iter()
+                .any(|(_, j)| self.value(*j) == value)
+    }
+
+    /// Check if an extra region goes through a cell more than once, so it can never hold
+    /// every value
+    ///
+    /// This happens to the diagonals of worlds that wrap around after a single sudoku,
+    /// where a sudoku shares its corner blocks with itself.
+    pub fn has_impossible_regions(&self) -> bool {
+        self.sudoku_coords().any(|coords| {
+            self.extra_regions(self.sudoku(coords))
+                .iter()
+                .any(|(_, indexes)| {
+                    (1..indexes.len()).any(|k| indexes[k..].contains(&indexes[k - 1]))
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sudokus::BoxSize;
+    use crate::topology::{GridTopology, Wrap};
+
+    fn diagonal_grid(n: usize, m: usize) -> SudokuGrid {
+        SudokuGrid::new(n, m).with_rules(Rules {
+            diagonals: true,
+            ..Rules::default()
+        })
+    }
+
+    fn window_grid(topology: GridTopology) -> SudokuGrid {
+        SudokuGrid::with_topology(topology).with_rules(Rules {
+            windows: true,
+            ..Rules::default()
+        })
+    }
+
+    #[test]
+    fn rules_bits_roundtrip() {
+        for bits in 0..16 {
+            let rules = Rules::from_bits(bits).unwrap();
+            assert_eq!(rules.to_bits(), bits);
+        }
+        assert!(Rules::from_bits(2).unwrap().windows);
+        assert!(Rules::from_bits(8).unwrap().anti_king);
+        assert_eq!(Rules::from_bits(16), None);
+        assert_eq!(Rules::from_bits(128), None);
+    }
+
+    #[test]
+    fn diagonal_regions() {
+        let sg = diagonal_grid(2, 2);
+        let sudoku = sg.sudoku((0, 0));
+        let regions = sg.extra_regions(sudoku);
+        assert_eq!(regions.len(), 2);
+
+        let layout = sg.layout();
+        let (main, anti) = (&regions[0].1, &regions[1].1);
+        assert_eq!(main[0], layout.index((0, 0), 0, 0));
+        assert_eq!(main[8], layout.index((0, 0), 8, 8));
+        assert_eq!(anti[0], layout.index((0, 0), 0, 8));
+        assert_eq!(anti[8], layout.index((0, 0), 8, 0));
+        // both diagonals go through the center
+        assert_eq!(main[4], anti[4]);
+
+        assert!(SudokuGrid::new(2, 2).extra_regions(sudoku).is_empty());
+    }
+
+    #[test]
+    fn diagonal_errors() {
+        let mut sg = diagonal_grid(2, 2);
+        let layout = sg.layout();
+        // same value on the main diagonal, but not in the same row, column, or block
+        let (a, b) = (layout.index((0, 0), 1, 1), layout.index((0, 0), 4, 4));
+        sg.cells[a] = 5;
+        sg.cells[b] = 5;
+        assert!(sg.cell_is_problematic((0, 0), a));
+        assert!(sg.peers(a).contains(&b));
+        assert_eq!(sg.conflicts(a)[0].region, RegionKind::Diagonal);
+
+        // the bottom right block of (0, 0) is the top left block of (0, 1), so its main
+        // diagonal continues there
+        let c = layout.index((0, 1), 2, 2);
+        assert_eq!(c, layout.index((0, 0), 8, 8));
+        let d = layout.index((0, 1), 5, 5);
+        sg.cells[c] = 7;
+        sg.cells[d] = 7;
+        sg.mark_errors();
+        let error = sg.boxes().error_flag();
+        assert!(sg.cells[d] & error > 0);
+
+        // without the rule there are no errors
+        let mut plain = SudokuGrid::with_layout(sg.layout());
+        plain.cells = sg.cells.iter().map(|c| c & !error).collect();
+        plain.mark_errors();
+        assert!(plain.cells.iter().all(|c| c & error == 0));
+    }
+
+    #[test]
+    fn diagonals_through_own_corners() {
+        // a sudoku that wraps around to itself has the same cells at both ends of its main
+        // diagonal
+        assert!(diagonal_grid(1, 1).has_impossible_regions());
+        assert!(diagonal_grid(3, 1).has_impossible_regions());
+        assert!(!diagonal_grid(2, 2).has_impossible_regions());
+        assert!(!SudokuGrid::new(1, 1).has_impossible_regions());
+        assert!(diagonal_grid(1, 1).solutions(1).is_empty());
+    }
+
+    #[test]
+    fn solve_diagonals() {
+        let topology = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(BoxSize::new(2, 2).unwrap())
+            .unwrap();
+        let sg = SudokuGrid::with_topology(topology).with_rules(Rules {
+            diagonals: true,
+            ..Rules::default()
+        });
+        let solution = sg.solutions(1).pop().expect("X-sudoku should be solvable");
+
+        let mut solved = SudokuGrid::with_layout(sg.layout()).with_rules(sg.rules());
+        solved.cells = solution;
+        assert!(solved.is_solved_all());
+        let sudoku = solved.sudoku((0, 0));
+        for (_, region) in solved.extra_regions(sudoku) {
+            assert!(solved.region(region).validate(false));
+        }
+    }
+
+    #[test]
+    fn window_regions() {
+        let sg = window_grid(GridTopology::new(2, 2, Wrap::Torus));
+        let sudoku = sg.sudoku((1, 0));
+        let regions = sg.extra_regions(sudoku);
+        assert_eq!(regions.len(), 4);
+        assert!(regions.iter().all(|(kind, _)| *kind == RegionKind::Window));
+
+        let layout = sg.layout();
+        let corners = regions
+            .iter()
+            .map(|(_, indexes)| (indexes[0], indexes[8]))
+            .collect::<Vec<_>>();
+        let at = |row, column| layout.index((1, 0), row, column);
+        assert_eq!(
+            corners,
+            [
+                (at(1, 1), at(3, 3)),
+                (at(1, 5), at(3, 7)),
+                (at(5, 1), at(7, 3)),
+                (at(5, 5), at(7, 7)),
+            ]
+        );
+
+        for (width, height, windows) in [(2, 2, 1), (3, 2, 1), (4, 4, 9)] {
+            let boxes = BoxSize::new(width, height).unwrap();
+            let sg = window_grid(
+                GridTopology::new(1, 1, Wrap::Bounded)
+                    .with_boxes(boxes)
+                    .unwrap(),
+            );
+            let regions = sg.extra_regions(sg.sudoku((0, 0)));
+            assert_eq!(regions.len(), windows);
+            assert!(regions.iter().all(|(_, r)| r.len() == boxes.values()));
+        }
+
+        let in_window = (0..sg.cells.len()).filter(|i| sg.in_window(*i)).count();
+        // neighbouring windows meet in one cell of every shared block
+        assert_eq!(in_window, 4 * 4 * 9 - 8);
+        assert!(sg.in_window(at(1, 1)));
+        assert!(!sg.in_window(at(4, 4)));
+        assert!(!SudokuGrid::new(2, 2).in_window(at(1, 1)));
+    }
+
+    #[test]
+    fn solve_windows() {
+        let sg = window_grid(GridTopology::new(2, 1, Wrap::Bounded));
+        let mut solved = SudokuGrid::with_layout(sg.layout()).with_rules(sg.rules());
+        solved.cells = sg.solutions(1).pop().expect("Windoku should be solvable");
+        assert!(solved.is_solved_all());
+
+        // a window clash is an error, even though the cells share no row, column, or block
+        let layout = solved.layout();
+        let (a, b) = (layout.index((0, 0), 1, 1), layout.index((0, 0), 3, 3));
+        let a_value = solved.value(a);
+        solved.cells[b] = a_value;
+        assert!(solved.cell_is_problematic((0, 0), b));
+        assert!(solved
+            .conflicts(b)
+            .iter()
+            .any(|c| c.index == a && c.region == RegionKind::Window));
+    }
+
+    fn chess_grid(anti_knight: bool, anti_king: bool) -> SudokuGrid {
+        SudokuGrid::with_wrap(2, 1, Wrap::Bounded).with_rules(Rules {
+            anti_knight,
+            anti_king,
+            ..Rules::default()
+        })
+    }
+
+    #[test]
+    fn knight_moves() {
+        let sg = chess_grid(true, false);
+        let layout = sg.layout();
+        let center = layout.index((0, 0), 4, 4);
+        let mut expected = [
+            (2, 3),
+            (2, 5),
+            (3, 2),
+            (3, 6),
+            (5, 2),
+            (5, 6),
+            (6, 3),
+            (6, 5),
+        ]
+        .map(|(row, column)| (RegionKind::Knight, layout.index((0, 0), row, column)));
+        expected.sort_unstable_by_key(|(_, j)| *j);
+        assert_eq!(sg.chess_peers(center), expected);
+        assert!(SudokuGrid::with_wrap(2, 1, Wrap::Bounded)
+            .chess_peers(center)
+            .is_empty());
+
+        // moves go both ways, and some of them cross into the other sudoku
+        let mut crossing = 0;
+        for i in 0..sg.cells.len() {
+            for (_, j) in sg.chess_peers(i) {
+                assert!(sg.chess_peers(*j).iter().any(|(_, k)| *k == i));
+                let sudokus = sg.sudokus_at_index(i);
+                crossing += sg.sudokus_at_index(*j).iter().all(|s| !sudokus.contains(s)) as usize;
+            }
+        }
+        assert!(crossing > 0);
+    }
+
+    #[test]
+    fn chess_moves_across_joins() {
+        for boxes in [BoxSize::new(2, 2).unwrap(), BoxSize::new(3, 2).unwrap()] {
+            for wrap in [Wrap::Torus, Wrap::KleinBottle] {
+                let Ok(topology) = GridTopology::new(2, 2, wrap).with_boxes(boxes) else {
+                    continue; // blocks that are not square can not be mirrored
+                };
+                let sg = SudokuGrid::with_topology(topology).with_rules(Rules {
+                    anti_knight: true,
+                    anti_king: true,
+                    ..Rules::default()
+                });
+                // these blocks leave no gray gaps, so every cell has all of its moves
+                for i in 0..sg.cells.len() {
+                    let peers = sg.chess_peers(i);
+                    let count = |kind| peers.iter().filter(|(k, _)| *k == kind).count();
+                    assert_eq!(count(RegionKind::Knight), 8, "{boxes:?} {wrap:?}");
+                    assert_eq!(count(RegionKind::King), 8, "{boxes:?} {wrap:?}");
+                    for (_, j) in peers {
+                        assert!(sg.chess_peers(*j).iter().any(|(_, k)| *k == i));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chess_errors() {
+        let mut sg = chess_grid(true, true);
+        let layout = sg.layout();
+        // a knight's move apart, in different rows, columns, and blocks
+        let (a, b) = (layout.index((0, 0), 1, 1), layout.index((0, 0), 3, 2));
+        sg.cells[a] = 6;
+        sg.cells[b] = 6;
+        assert!(sg.cell_is_problematic((0, 0), b));
+        assert!(sg.chess_is_problematic(b));
+        assert!(!sg.chess_is_problematic(layout.index((0, 0), 1, 2)));
+        assert!(sg.extra_regions(sg.sudoku((0, 0))).is_empty());
+        assert!(sg.peers(a).contains(&b));
+        assert_eq!(sg.conflicts(b)[0].region, RegionKind::Knight);
+
+        // diagonal neighbours in different blocks are a king's move apart
+        let (c, d) = (layout.index((0, 0), 5, 5), layout.index((0, 0), 6, 6));
+        sg.cells[c] = 2;
+        sg.cells[d] = 2;
+        assert_eq!(sg.conflicts(c)[0].region, RegionKind::King);
+        sg.mark_errors();
+        assert!(sg.cells[d] & sg.boxes().error_flag() > 0);
+        assert!(!SudokuGrid::new(2, 1).peers(c).contains(&d));
+    }
+
+    #[test]
+    fn solve_chess() {
+        for (anti_knight, anti_king) in [(true, false), (false, true), (true, true)] {
+            let sg = chess_grid(anti_knight, anti_king);
+            let mut solved = sg.empty_like();
+            solved.cells = sg
+                .solutions(1)
+                .pop()
+                .expect("chess rules should be solvable");
+            assert!(solved.is_solved_all());
+        }
+
+        // a classic solution has equal values a king's move apart
+        let mut classic = SudokuGrid::with_wrap(2, 1, Wrap::Bounded);
+        classic.cells = classic.solutions(1).pop().expect("should be solvable");
+        assert!(classic.is_solved_all());
+        let mut solved = chess_grid(false, true);
+        solved.cells = classic.cells;
+        assert!(!solved.is_solved_all());
+    }
+}
@@ -0,0 +1,522 @@
+//! Backtracking solver for the whole world at once
+//!
+//! Unlike [`SudokuGrid::depth_first_solve`], which solves one sudoku at a time, this
+//! solver takes every constraint of every sudoku into account, so it can tell whether a
+//! world has no, one, or multiple solutions.
+
+use crate::cages::sum_is_possible;
+use crate::sudokus::{BoxSize, SudokuGrid};
+
+#[derive(Clone)]
+pub struct Solver {
+    /// Other cells sharing a row, column, block, or extra region with each cell
+    peers: Box<[Box<[usize]>]>,
+    /// Every row, column, block, and extra region of the world (shared blocks only once)
+    regions: Box<[Box<[usize]>]>,
+    /// Bitmap of the values 1 to the number of values of the grid
+    all_values: u32,
+    /// Sum and cells of every killer cage
+    cages: Box<[(u32, Box<[usize]>)]>,
+    /// Index in `cages` of the cage of each cell (empty without cages)
+    cage_of: Box<[Option<usize>]>,
+    /// Current value of each cell (0 if empty)
+    values: Box<[u8]>,
+    /// Number of peers containing each value, for each cell
+    blocked: Box<[[u8; BoxSize::MAX_VALUES + 1]]>,
+    /// Bitmap of values that are not blocked, for each cell (bit `v` for value `v`)
+    candidates: Box<[u32]>,
+    /// Set if two peers contain the same value from the start, or if the grid has a region
+    /// that can never be completed (see [`SudokuGrid::has_impossible_regions`])
+    conflicting: bool,
+}
+
+/// Result of [`Solver::closest_solution`]
+pub struct Closest {
+    /// The closest solution found (only containing values, without flags)
+    pub solution: Option<Box<[u8]>>,
+    /// Number of cells where the solution differs from the preferred values
+    pub cost: usize,
+    /// Nodes left to visit
+    nodes: usize,
+    /// Set if the search was stopped before it was complete, so a closer solution (or any
+    /// solution at all) might exist
+    pub exhausted: bool,
+}
+
+impl Solver {
+    /// Create a solver that starts from the (non-zero) values in the grid
+    pub fn new(sg: &SudokuGrid) -> Self {
+        let len = sg.cells.len();
+        let values = sg.boxes().values();
+        // the values 1 to `values`
+        let all_candidates = ((1 << values) - 1) << 1;
+        let mut regions = Vec::new();
+        for sudoku in sg.sudoku_coords().map(|coords| sg.sudoku(coords)) {
+            for k in 0..values {
+                regions.push(sg.row(sudoku, k).indexes().collect::<Box<[_]>>());
+                regions.push(sg.column(sudoku, k).indexes().collect());
+                regions.push(sg.block(sudoku, k).indexes().collect());
+            }
+            regions.extend(sg.extra_regions(sudoku).iter().map(|(_, r)| r.clone()));
+        }
+        for region in regions.iter_mut() {
+            region.sort_unstable();
+        }
+        regions.sort_unstable();
+        regions.dedup();
+
+        let mut solver = Solver {
+            peers: (0..len).map(|i| sg.peers(i).into()).collect(),
+            regions: regions.into(),
+            all_values: all_candidates,
+            cages: sg
+                .cages()
+                .iter()
+                .map(|cage| (cage.sum, cage.cells.clone()))
+                .collect(),
+            cage_of: Box::new([]),
+            values: vec![0; len].into(),
+            blocked: vec![[0; BoxSize::MAX_VALUES + 1]; len].into(),
+            candidates: vec![all_candidates; len].into(),
+            conflicting: sg.has_impossible_regions(),
+        };
+
+        if !sg.cages().is_empty() {
+            let mut cage_of = vec![None; len];
+            for (k, (sum, cells)) in solver.cages.iter().enumerate() {
+                for i in cells.iter() {
+                    cage_of[*i] = Some(k);
+                }
+                // cages that are already full have to add up to their sum (others are
+                // checked when their cells get filled)
+                let total = cells.iter().map(|c| sg.value(*c) as u32).sum::<u32>();
+                if total > *sum || (cells.iter().all(|c| sg.value(*c) > 0) && total != *sum) {
+                    solver.conflicting = true;
+                }
+            }
+            solver.cage_of = cage_of.into();
+        }
+
+        for i in 0..len {
+            let value = sg.value(i);
+            if value as usize > values {
+                // not a value of this grid at all
+                solver.conflicting = true;
+            } else if value > 0 {
+                if solver.candidates[i] & (1 << value) == 0 {
+                    solver.conflicting = true;
+                }
+                solver.assign(i, value);
+            }
+        }
+
+        solver
+    }
+
+    fn assign(&mut self, i: usize, value: u8) {
+        self.values[i] = value;
+        for p in self.peers[i].iter() {
+            let blocked = &mut self.blocked[*p][value as usize];
+            *blocked += 1;
+            if *blocked == 1 {
+                self.candidates[*p] &= !(1 << value);
+            }
+        }
+    }
+
+    fn unassign(&mut self, i: usize) {
+        let value = self.values[i];
+        self.values[i] = 0;
+        for p in self.peers[i].iter() {
+            let blocked = &mut self.blocked[*p][value as usize];
+            *blocked -= 1;
+            if *blocked == 0 {
+                self.candidates[*p] |= 1 << value;
+            }
+        }
+    }
+
+    /// Get the candidates of the empty cell at index i that still leave a way to complete
+    /// its cage
+    fn cage_candidates(&self, i: usize) -> u32 {
+        let candidates = self.candidates[i];
+        let Some(cage) = self.cage_of.get(i).copied().flatten() else {
+            return candidates;
+        };
+        let (sum, cells) = &self.cages[cage];
+        let (mut used, mut total, mut empty) = (0, 0, 0);
+        for c in cells.iter() {
+            match self.values[*c] {
+                0 => empty += 1,
+                value => {
+                    used |= 1 << value;
+                    total += value as u32;
+                }
+            }
+        }
+        let mut allowed = 0;
+        let mut rest = candidates & !used;
+        while rest != 0 {
+            let bit = rest & rest.wrapping_neg();
+            rest &= rest - 1;
+            let value = bit.trailing_zeros();
+            if total + value <= *sum
+                && sum_is_possible(
+                    self.all_values & !used & !bit,
+                    empty - 1,
+                    sum - total - value,
+                )
+            {
+                allowed |= bit;
+            }
+        }
+        allowed
+    }
+
+    /// Find the empty cell to fill next, with the values to try in it
+    ///
+    /// This is the cell with the fewest candidates (leaving out values its cage can't be
+    /// completed with), unless a region has a value that fits in only one of its cells
+    /// (which is then the only value to try), or a value that fits nowhere (then there is
+    /// nothing to try). Returns `None` if every cell is filled.
+    fn most_constrained_cell(&self) -> Option<(usize, u32)> {
+        let mut best = None;
+        let mut best_candidates = 0;
+        let mut best_count = u32::MAX;
+        for (i, value) in self.values.iter().enumerate() {
+            if *value == 0 {
+                let candidates = self.cage_candidates(i);
+                let count = candidates.count_ones();
+                if count < best_count {
+                    best = Some(i);
+                    best_candidates = candidates;
+                    best_count = count;
+                    if count <= 1 {
+                        break;
+                    }
+                }
+            }
+        }
+        let best = best?;
+        if best_count <= 1 {
+            return Some((best, best_candidates));
+        }
+
+        for region in self.regions.iter() {
+            // values placed in the region, and values fitting in at least one or two cells
+            let (mut placed, mut once, mut twice) = (0, 0, 0);
+            for i in region.iter() {
+                match self.values[*i] {
+                    0 => {
+                        twice |= once & self.candidates[*i];
+                        once |= self.candidates[*i];
+                    }
+                    value => placed |= 1 << value,
+                }
+            }
+            let missing = self.all_values & !placed;
+            if missing & !once != 0 {
+                return Some((best, 0));
+            }
+            let single = missing & once & !twice;
+            if single != 0 {
+                let value = single & single.wrapping_neg();
+                let i = region
+                    .iter()
+                    .find(|i| self.values[**i] == 0 && self.candidates[**i] & value != 0);
+                let i = *i.unwrap();
+                return Some((i, value & self.cage_candidates(i)));
+            }
+        }
+        Some((best, best_candidates))
+    }
+
+    /// Returns false if the search ran out of nodes before it was complete
+    fn search(&mut self, limit: usize, nodes: &mut usize, solutions: &mut Vec<Box<[u8]>>) -> bool {
+        if *nodes == 0 {
+            return false;
+        }
+        *nodes -= 1;
+        let Some((i, mut candidates)) = self.most_constrained_cell() else {
+            solutions.push(self.values.clone());
+            return true;
+        };
+
+        while candidates != 0 && solutions.len() < limit {
+            let value = candidates.trailing_zeros() as u8;
+            candidates &= candidates - 1;
+
+            self.assign(i, value);
+            let complete = self.search(limit, nodes, solutions);
+            self.unassign(i);
+            if !complete {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Lower bound for the cost of completing the search: preferred values that are blocked
+    fn forced_cost(&self, preferred: &[u8]) -> usize {
+        (0..self.values.len())
+            .filter(|i| {
+                let p = preferred[*i];
+                self.values[*i] == 0 && p > 0 && self.candidates[*i] & (1 << p) == 0
+            })
+            .count()
+    }
+
+    fn search_closest(&mut self, preferred: &[u8], cost: usize, closest: &mut Closest) {
+        if closest.nodes == 0 {
+            closest.exhausted = true;
+            return;
+        }
+        closest.nodes -= 1;
+        if cost + self.forced_cost(preferred) >= closest.cost {
+            return;
+        }
+        let Some((i, mut candidates)) = self.most_constrained_cell() else {
+            closest.solution = Some(self.values.clone());
+            closest.cost = cost;
+            return;
+        };
+
+        // try the preferred value first, every other value adds to the cost
+        let p = preferred[i];
+        if p > 0 && candidates & (1 << p) > 0 {
+            candidates &= !(1 << p);
+            self.assign(i, p);
+            self.search_closest(preferred, cost, closest);
+            self.unassign(i);
+        }
+        let cost = cost + (p > 0) as usize;
+        while candidates != 0 && cost < closest.cost && !closest.exhausted {
+            let value = candidates.trailing_zeros() as u8;
+            candidates &= candidates - 1;
+
+            self.assign(i, value);
+            self.search_closest(preferred, cost, closest);
+            self.unassign(i);
+        }
+    }
+
+    fn search_preferred(&mut self, preferred: &[u8], nodes: &mut usize) -> Option<Box<[u8]>> {
+        if *nodes == 0 {
+            return None;
+        }
+        *nodes -= 1;
+        let Some((i, mut candidates)) = self.most_constrained_cell() else {
+            return Some(self.values.clone());
+        };
+
+        // try the preferred value first, then every other value
+        let p = preferred[i];
+        let first = (p > 0 && candidates & (1 << p) > 0).then_some(p);
+        if let Some(p) = first {
+            candidates &= !(1 << p);
+        }
+        let others = std::iter::from_fn(|| {
+            let value = candidates.trailing_zeros() as u8;
+            candidates &= candidates.checked_sub(1)?;
+            Some(value)
+        });
+        for value in first.into_iter().chain(others) {
+            self.assign(i, value);
+            let solution = self.search_preferred(preferred, nodes);
+            self.unassign(i);
+            if solution.is_some() {
+                return solution;
+            }
+        }
+        None
+    }
+
+    /// Find any solution, trying the preferred value (0 for no preference) of every cell
+    /// first, visiting at most `max_nodes` nodes of the search tree
+    ///
+    /// Unlike [`Solver::closest_solution`], this stops at the first solution, so random
+    /// preferred values give a random solution quickly.
+    pub fn preferred_solution(mut self, preferred: &[u8], max_nodes: usize) -> Option<Box<[u8]>> {
+        let mut nodes = max_nodes;
+        if self.conflicting {
+            return None;
+        }
+        self.search_preferred(preferred, &mut nodes)
+    }
+
+    /// Find the solution that differs from the preferred values (0 for no preference) in as
+    /// few cells as possible, visiting at most `max_nodes` nodes of the search tree
+    pub fn closest_solution(mut self, preferred: &[u8], max_nodes: usize) -> Closest {
+        let mut closest = Closest {
+            solution: None,
+            cost: usize::MAX,
+            nodes: max_nodes,
+            exhausted: false,
+        };
+        if !self.conflicting {
+            self.search_closest(preferred, 0, &mut closest);
+        }
+        closest
+    }
+
+    /// Find up to `limit` solutions (only containing values, without flags), visiting at
+    /// most `max_nodes` nodes of the search tree
+    ///
+    /// Returns `None` if the search ran out of nodes before it found `limit` solutions or
+    /// made sure that there are no more.
+    pub fn solutions_within(mut self, limit: usize, max_nodes: usize) -> Option<Vec<Box<[u8]>>> {
+        let mut solutions = Vec::new();
+        let mut nodes = max_nodes;
+        if !self.conflicting && !self.search(limit, &mut nodes, &mut solutions) {
+            return None;
+        }
+        Some(solutions)
+    }
+}
+
+impl SudokuGrid {
+    /// Find up to `limit` solutions for the whole world, keeping all non-zero cells, however
+    /// long that takes (the app always limits the search, see
+    /// [`SudokuGrid::solutions_within`])
+    #[cfg(test)]
+    pub fn solutions(&self, limit: usize) -> Vec<Box<[u8]>> {
+        Solver::new(self)
+            .solutions_within(limit, usize::MAX)
+            .expect("the search can not run out of nodes")
+    }
+
+    /// Find up to `limit` solutions for the whole world, keeping all non-zero cells, or
+    /// `None` if the search needs more than `max_nodes` nodes (see
+    /// [`Solver::solutions_within`])
+    pub fn solutions_within(&self, limit: usize, max_nodes: usize) -> Option<Vec<Box<[u8]>>> {
+        Solver::new(self).solutions_within(limit, max_nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{GridTopology, Wrap};
+
+    #[test]
+    fn solve_empty_world() {
+        for (n, m, wrap) in [
+            (1, 1, Wrap::Torus),
+            (2, 1, Wrap::Torus),
+            (2, 3, Wrap::Torus),
+            (1, 1, Wrap::Bounded),
+            (3, 2, Wrap::Bounded),
+            (3, 2, Wrap::Mobius),
+            (2, 3, Wrap::KleinBottle),
+        ] {
+            let mut sg = SudokuGrid::with_wrap(n, m, wrap);
+            let solutions = sg.solutions(1);
+            assert_eq!(solutions.len(), 1);
+            sg.cells = solutions[0].clone();
+            assert!(sg.is_solved_all());
+        }
+    }
+
+    #[test]
+    fn solve_box_sizes() {
+        for (width, height, n, m, wrap) in [
+            (2, 2, 2, 2, Wrap::Torus),
+            (3, 2, 2, 1, Wrap::Bounded),
+            (2, 3, 2, 2, Wrap::Torus),
+            (4, 4, 1, 1, Wrap::Bounded),
+            (4, 4, 2, 1, Wrap::KleinBottle),
+        ] {
+            let boxes = BoxSize::new(width, height).unwrap();
+            let topology = GridTopology::new(n, m, wrap).with_boxes(boxes).unwrap();
+            let mut sg = SudokuGrid::with_topology(topology);
+            let solutions = sg.solutions(1);
+            assert_eq!(solutions.len(), 1);
+            sg.cells = solutions[0].clone();
+            assert!(sg.is_solved_all());
+            assert!(sg
+                .cells
+                .iter()
+                .all(|c| (1..=boxes.values() as u8).contains(c)));
+        }
+    }
+
+    #[test]
+    fn solutions_within_nodes() {
+        let sg = SudokuGrid::new(1, 1);
+        assert_eq!(sg.solutions_within(2, 10), None);
+        let solutions = sg.solutions_within(2, 1000).unwrap();
+        assert_eq!(solutions, sg.solutions(2));
+
+        // a solved grid only needs a single node
+        let mut solved = sg.empty_like();
+        solved.cells = solutions[0].clone();
+        assert_eq!(
+            solved.solutions_within(2, 1),
+            Some(vec![solutions[0].clone()])
+        );
+    }
+
+    #[test]
+    fn preferred_solution() {
+        let boxes = BoxSize::new(3, 2).unwrap();
+        let topology = GridTopology::new(2, 2, Wrap::Torus)
+            .with_boxes(boxes)
+            .unwrap();
+        let sg = SudokuGrid::with_topology(topology);
+        let solution = sg.solutions(1).remove(0);
+
+        // a solution is found as it is, and without preferences it is still a solution
+        let preferred = Solver::new(&sg).preferred_solution(&solution, 1000);
+        assert_eq!(preferred.as_ref(), Some(&solution));
+        let mut solved = SudokuGrid::with_layout(sg.layout());
+        solved.cells = Solver::new(&sg)
+            .preferred_solution(&vec![0; sg.cells.len()], 1000)
+            .unwrap();
+        assert!(solved.is_solved_all());
+
+        // running out of nodes
+        assert_eq!(Solver::new(&sg).preferred_solution(&solution, 10), None);
+    }
+
+    #[test]
+    fn solution_count() {
+        let mut sg = SudokuGrid::new(2, 2);
+        sg.cells = sg.solutions(1).remove(0);
+
+        // a single missing cell can only have one value
+        let solution = sg.cells.clone();
+        sg.cells[17] = 0;
+        assert_eq!(sg.solutions(2), vec![solution]);
+
+        // nothing given at all has many solutions
+        assert_eq!(SudokuGrid::new(2, 2).solutions(2).len(), 2);
+
+        // conflicting givens have none
+        let mut sg = SudokuGrid::new(2, 2);
+        sg.cells[0] = 5;
+        sg.cells[1] = 5 + 16;
+        assert!(sg.solutions(1).is_empty());
+    }
+
+    #[test]
+    fn solve_killer() {
+        let mut sg = SudokuGrid::with_wrap(1, 1, Wrap::Bounded);
+        sg.cells = sg.solutions(1).remove(0);
+        let cages = sg.random_cages(4, |n| n / 2);
+
+        // without any givens, the solution still has to add up to every cage
+        let empty = SudokuGrid::with_layout(sg.layout())
+            .with_cages(cages.clone())
+            .unwrap();
+        let mut solved = empty.empty_like();
+        solved.cells = empty.solutions(1).remove(0);
+        assert!(solved.is_solved_all());
+
+        // a full cage with the wrong sum has no solution
+        let mut cages = cages;
+        cages[0].sum += 1;
+        let mut wrong = sg.empty_like().with_cages(cages).unwrap();
+        wrong.cells = sg.cells.clone();
+        assert!(wrong.solutions(1).is_empty());
+    }
+}
@@ -1,7 +1,16 @@
 #![allow(dead_code)] // for development, go away annoying squiggly lines
 
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::{wasm_bindgen, JsError};
+
+use crate::cages::{Cage, CageError};
+use crate::layout::Layout;
+use crate::rules::{ChessPeers, ExtraRegions, Rules};
+use crate::topology::{EdgeOverlap, GridTopology, Preset, Topology, Wrap};
+
 /// Bitmap of seen values
-struct Seen(u16);
+struct Seen(u32);
 
 impl Seen {
     fn new() -> Self {
@@ -13,12 +22,13 @@ impl Seen {
     }
 
     fn add(&mut self, value: u8) {
-        debug_assert!(value < 16);
+        debug_assert!(value < 32);
         self.0 |= 1 << value;
     }
 }
 
-/// A row, column, or block, described by a list of its 9 indexes
+/// A row, column, block, or extra region (see [`SudokuGrid::extra_regions`]), described
+/// by a list of its indexes
 pub struct Region<'a, T: Iterator<Item = usize>>(&'a SudokuGrid, T);
 
 impl<'a, T: Iterator<Item = usize>> Region<'a, T> {
@@ -30,17 +40,19 @@ impl<'a, T: Iterator<Item = usize>> Region<'a, T> {
         self.1
     }
 
-    /// Check if region contains the numbers 1 to 9 (assuming the sequence is 9 long)
+    /// Check if region contains the numbers 1 to 9 (or up to the number of values of the
+    /// grid, see [`BoxSize::values`]), assuming the sequence is that long
     ///
     /// Ignores empty (0) cells if partial is true
     pub fn validate(self, partial: bool) -> bool {
         let mut seen = Seen::new();
+        let values = self.0.boxes().values() as u8;
         for i in self.1 {
-            let value = self.0.cells[i] & 15;
+            let value = self.0.value(i);
             if partial && value == 0 {
                 continue;
             }
-            if value == 0 || value > 9 || seen.contains(value) {
+            if value == 0 || value > values || seen.contains(value) {
                 return false;
             }
             seen.add(value);
@@ -52,7 +64,7 @@ impl<'a, T: Iterator<Item = usize>> Region<'a, T> {
     pub fn has_duplicate_value(self, value: u8) -> bool {
         let mut seen = false;
         for i in self.1 {
-            if (self.0.cells[i] & 15) == value {
+            if self.0.value(i) == value {
                 if seen {
                     return true;
                 } else {
@@ -64,30 +76,92 @@ impl<'a, T: Iterator<Item = usize>> Region<'a, T> {
     }
 }
 
+/// Kind of region two cells can share
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Row,
+    Column,
+    Block,
+    /// A main diagonal of an X-sudoku (see [`Rules::diagonals`])
+    Diagonal,
+    /// A window of a Windoku (see [`Rules::windows`])
+    Window,
+    /// A cage of a killer sudoku (see [`Cage`])
+    Cage,
+    /// A knight's move apart (see [`Rules::anti_knight`])
+    Knight,
+    /// A king's move apart (see [`Rules::anti_king`])
+    King,
+}
+
+/// Solution that does not have a value for every cell (see [`SudokuGrid::mark_wrong`])
+#[derive(Debug, PartialEq)]
+pub struct SolutionLength {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl std::fmt::Display for SolutionLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "solution has {} cells, but the world has {}",
+            self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for SolutionLength {}
+
+/// Another cell with the same value in a region of a sudoku
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    pub index: usize,
+    pub region: RegionKind,
+    pub sudoku: Coords,
+}
+
 #[derive(Debug, Clone)]
 pub struct Sudoku {
-    pub block_start: [usize; 9],
+    pub block_start: Box<[usize]>,
+    /// Blocks that are stored mirrored (see [`Layout::is_mirrored`])
+    pub mirrored: Box<[bool]>,
+    boxes: BoxSize,
     x: usize,
     y: usize,
 }
 
 impl Sudoku {
-    fn indexes(&self) -> impl Iterator<Item = usize> + use<'_> {
-        let block_order = [
-            BOTTOM_LEFT_BLOCK,
-            BOTTOM_RIGHT_BLOCK,
-            TOP_LEFT_BLOCK,
-            TOP_RIGHT_BLOCK,
-            BOTTOM_CENTER_BLOCK,
-            TOP_CENTER_BLOCK,
-            MIDDLE_LEFT_BLOCK,
-            MIDDLE_RIGHT_BLOCK,
-            MIDDLE_CENTER_BLOCK, // testing showed that doing center last gives fasted solves
-        ];
+    /// Get the (row, column) in this sudoku of cell index i in block `block`
+    pub fn position(&self, block: usize, i: usize) -> (usize, usize) {
+        let offset = self
+            .boxes
+            .stored_offset(self.mirrored[block], i - self.block_start[block]);
+        self.boxes.position(block, offset)
+    }
+
+    pub fn indexes(&self) -> impl Iterator<Item = usize> + use<'_> {
+        let block_order = if self.boxes == BoxSize::CLASSIC {
+            vec![
+                BOTTOM_LEFT_BLOCK,
+                BOTTOM_RIGHT_BLOCK,
+                TOP_LEFT_BLOCK,
+                TOP_RIGHT_BLOCK,
+                BOTTOM_CENTER_BLOCK,
+                TOP_CENTER_BLOCK,
+                MIDDLE_LEFT_BLOCK,
+                MIDDLE_RIGHT_BLOCK,
+                MIDDLE_CENTER_BLOCK, // testing showed that doing center last gives fasted solves
+            ]
+        } else {
+            (0..self.boxes.values()).collect()
+        };
+        let values = self.boxes.values();
         block_order
             .into_iter()
             .map(|b| self.block_start[b])
-            .flat_map(|b| b..(b + 9))
+            .flat_map(move |b| b..(b + values))
     }
 
     pub fn coords(&self) -> Coords {
@@ -95,7 +169,7 @@ impl Sudoku {
     }
 }
 
-// block indexes
+// block indexes (of sudokus with 3x3 blocks)
 pub const TOP_LEFT_BLOCK: usize = 0;
 pub const TOP_CENTER_BLOCK: usize = 1;
 pub const TOP_RIGHT_BLOCK: usize = 2;
@@ -106,56 +180,237 @@ pub const BOTTOM_LEFT_BLOCK: usize = 6;
 pub const BOTTOM_CENTER_BLOCK: usize = 7;
 pub const BOTTOM_RIGHT_BLOCK: usize = 8;
 
-pub const BLOCK_MEMORY_ORDER: [usize; 7] = [
-    TOP_CENTER_BLOCK,
-    MIDDLE_LEFT_BLOCK,
-    MIDDLE_CENTER_BLOCK,
-    MIDDLE_RIGHT_BLOCK,
-    BOTTOM_LEFT_BLOCK,
-    BOTTOM_CENTER_BLOCK,
-    BOTTOM_RIGHT_BLOCK,
-];
+/// Most blocks a row or column can go through (for blocks that are 2 cells wide or high)
+const MAX_LINE_BLOCKS: usize = BoxSize::MAX_VALUES / 2;
+
+/// Size of the blocks of every sudoku in a world
+///
+/// A sudoku with blocks of `width` by `height` cells has `height` blocks in every row of
+/// blocks and `width` in every column, so it has `width * height` rows, columns, and
+/// blocks, which all hold the values 1 to `width * height`. Blocks are numbered like
+/// cells: left to right, then top to bottom.
+///
+/// Cells store their value in the lowest 4 bits, with the flags above it (user entered
+/// 16, error 32, wrong 64). Grids with more than 15 values need 5 bits for the value, so
+/// their flags are one bit higher (32, 64 and 128, see [`BoxSize::value_bits`]).
+///
+/// The grid, solver, generator, the save and text formats, the SVG export, and the text
+/// display work with any block size; the WebGL view only draws sudokus with 3x3 blocks.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for BoxSize {
+    fn default() -> Self {
+        BoxSize::CLASSIC
+    }
+}
+
+#[wasm_bindgen]
+impl BoxSize {
+    /// Fails if the block size is not supported (see [`BoxSize::is_valid`])
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize) -> Result<BoxSize, JsError> {
+        let boxes = BoxSize { width, height };
+        if !boxes.is_valid() {
+            return Err(JsError::new(&format!(
+                "unsupported block size {width}x{height}"
+            )));
+        }
+        Ok(boxes)
+    }
+
+    /// Whether blocks are at least 2 cells wide and high, with at most
+    /// [`BoxSize::MAX_VALUES`] cells
+    pub fn is_valid(self) -> bool {
+        self.width >= 2 && self.height >= 2 && self.values() <= BoxSize::MAX_VALUES
+    }
+
+    /// Number of values, which is also the number of cells in a row, column, or block, and
+    /// the number of blocks in a sudoku
+    pub fn values(self) -> usize {
+        self.width * self.height
+    }
+}
+
+impl BoxSize {
+    /// Blocks of 3x3 cells, holding the values 1 to 9
+    pub const CLASSIC: BoxSize = BoxSize {
+        width: 3,
+        height: 3,
+    };
+
+    /// Most values a grid can hold (16, for 4x4 blocks)
+    pub const MAX_VALUES: usize = 16;
+
+    /// Number of blocks in every row of blocks
+    pub fn across(self) -> usize {
+        self.height
+    }
+
+    /// Number of rows of blocks
+    pub fn down(self) -> usize {
+        self.width
+    }
+
+    pub fn top_right_block(self) -> usize {
+        self.across() - 1
+    }
+
+    pub fn bottom_left_block(self) -> usize {
+        (self.down() - 1) * self.across()
+    }
+
+    pub fn bottom_right_block(self) -> usize {
+        self.values() - 1
+    }
+
+    /// Whether a block is in a corner of the sudoku
+    pub fn is_corner(self, block: usize) -> bool {
+        [
+            TOP_LEFT_BLOCK,
+            self.top_right_block(),
+            self.bottom_left_block(),
+            self.bottom_right_block(),
+        ]
+        .contains(&block)
+    }
+
+    /// Blocks that every sudoku stores first, one after the other: all blocks except the
+    /// top left and top right ones, which are usually shared with the sudoku above it
+    pub fn block_memory_order(self) -> impl Iterator<Item = usize> {
+        (0..self.values()).filter(move |b| *b != TOP_LEFT_BLOCK && *b != self.top_right_block())
+    }
+
+    /// Get the (row, column) in a sudoku of a cell at an offset in a block
+    pub fn position(self, block: usize, offset: usize) -> (usize, usize) {
+        (
+            (block / self.across()) * self.height + offset / self.width,
+            (block % self.across()) * self.width + offset % self.width,
+        )
+    }
+
+    /// Get the block and the offset in it of a cell at a row and column of a sudoku
+    pub fn block_and_offset(self, row: usize, column: usize) -> (usize, usize) {
+        (
+            column / self.width + (row / self.height) * self.across(),
+            column % self.width + (row % self.height) * self.width,
+        )
+    }
+
+    /// Mirror the offset of a cell in a block across the diagonal from its bottom left to
+    /// its top right corner (how a block looks in a mirrored sudoku)
+    ///
+    /// Blocks are mirrored in a sudoku the same way, so this only works for square blocks.
+    pub fn mirror(self, offset: usize) -> usize {
+        debug_assert_eq!(
+            self.width, self.height,
+            "only square blocks can be mirrored"
+        );
+        let side = self.width;
+        (side - 1 - offset % side) * side + side - 1 - offset / side
+    }
+
+    /// Offset of a cell in a block, as stored
+    fn stored_offset(self, mirrored: bool, offset: usize) -> usize {
+        if mirrored {
+            self.mirror(offset)
+        } else {
+            offset
+        }
+    }
+
+    /// Number of bits of a cell that hold its value (4, or 5 for more than 15 values)
+    pub fn value_bits(self) -> u32 {
+        if self.values() > 15 {
+            5
+        } else {
+            4
+        }
+    }
+
+    /// Bits of a cell that hold its value
+    pub fn value_mask(self) -> u8 {
+        (1 << self.value_bits()) - 1
+    }
+
+    /// Flag of values entered by the user (16, or 32 for more than 15 values)
+    pub fn user_flag(self) -> u8 {
+        1 << self.value_bits()
+    }
+
+    /// Flag of values that clash with another value (see [`SudokuGrid::mark_errors`])
+    pub fn error_flag(self) -> u8 {
+        2 << self.value_bits()
+    }
+
+    /// Flag of user entries that differ from the solution (see [`SudokuGrid::mark_wrong`])
+    pub fn wrong_flag(self) -> u8 {
+        4 << self.value_bits()
+    }
+}
 
 pub struct SudokuGrid {
-    /// Stores all cells without overlap (so 7 * 9 cells per sudoku)
+    /// Stores all cells without overlap (so 7 * 9 cells per sudoku with 3x3 blocks, see
+    /// [`Layout::new`])
     pub cells: Box<[u8]>,
+    /// Every sudoku of the world, ordered by `x + y * n`
     sudokus: Box<[Sudoku]>,
     pub n: usize,
     pub m: usize,
+    layout: Rc<Layout>,
+    rules: Rules,
+    /// Regions the rules add to every sudoku (empty without rules, see
+    /// [`SudokuGrid::extra_regions`])
+    extra_regions: ExtraRegions,
+    /// Whether every cell is part of a window (empty without windows, see
+    /// [`SudokuGrid::in_window`])
+    window_cells: Rc<[bool]>,
+    cages: Rc<[Cage]>,
+    /// Index in `cages` of the cage every cell is part of (empty without cages)
+    cage_indexes: Rc<[Option<usize>]>,
+    /// Cells a chess move away from every cell (empty without chess rules, see
+    /// [`SudokuGrid::chess_peers`])
+    chess_peers: ChessPeers,
 }
 
 #[derive(Debug)]
 pub struct NoSolution;
 
+impl std::fmt::Display for NoSolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "no solution found")
+    }
+}
+
+impl std::error::Error for NoSolution {}
+
 pub type Coords = (usize, usize);
 
 pub struct DfsBlock<'a> {
     indexes: Box<[usize]>,
     sudoku_coords: Coords,
-    other_sudoku_coords: Option<Coords>,
+    other_sudokus: Vec<Coords>,
     random: &'a [u8],
-    value_index: [usize; 9],
+    value_index: Box<[usize]>,
     backtracks: u64,
     i: usize,
 }
 
 impl<'a> DfsBlock<'a> {
     pub fn new(sg: &SudokuGrid, sudoku_coords: Coords, i: usize, random: &'a [u8]) -> Self {
-        let indexes = sg.block(sg.sudoku(sudoku_coords), i).indexes().collect();
-
-        let other_sudoku_coords = match i {
-            TOP_LEFT_BLOCK => Some((sudoku_coords.0, (sudoku_coords.1 + 1) % sg.m)),
-            TOP_RIGHT_BLOCK => Some(((sudoku_coords.0 + 1) % sg.n, sudoku_coords.1)),
-            BOTTOM_LEFT_BLOCK => Some(((sudoku_coords.0 + sg.n - 1) % sg.n, sudoku_coords.1)),
-            BOTTOM_RIGHT_BLOCK => Some((sudoku_coords.0, (sudoku_coords.1 + sg.m - 1) % sg.m)),
-            _ => None,
-        };
+        let indexes: Box<[usize]> = sg.block(sg.sudoku(sudoku_coords), i).indexes().collect();
+
+        let other_sudokus = sg.shared_with(sudoku_coords, i);
 
         DfsBlock {
+            value_index: vec![0; indexes.len()].into(),
             indexes,
             sudoku_coords,
-            other_sudoku_coords,
-            value_index: [0; 9],
+            other_sudokus,
             random,
             backtracks: 0,
             i: 0,
@@ -164,7 +419,7 @@ impl<'a> DfsBlock<'a> {
 
     fn backtrack(&mut self, sg: &mut SudokuGrid) -> Result<(), NoSolution> {
         self.backtracks += 1;
-        while self.value_index[self.i] >= 8 {
+        while self.value_index[self.i] >= self.random.len() - 1 {
             sg.cells[self.indexes[self.i]] = 0;
             self.value_index[self.i] = 0;
             if self.i == 0 {
@@ -178,25 +433,27 @@ impl<'a> DfsBlock<'a> {
     }
 
     pub fn next_solution(&mut self, sg: &mut SudokuGrid) -> Result<u64, NoSolution> {
-        if self.i >= 9 {
-            self.i = 8;
+        let (len, last_value) = (self.indexes.len(), self.random.len() - 1);
+        if self.i >= len {
+            self.i = len - 1;
             self.value_index[self.i] += 1;
             println!("i: {}, vi: {}", self.i, self.value_index[self.i]);
-            if self.value_index[self.i] >= 8 {
+            if self.value_index[self.i] >= last_value {
                 self.backtrack(sg)?
             }
             println!("i: {}", self.i);
         }
 
-        while self.i < 9 {
+        while self.i < len {
             let index = self.indexes[self.i];
             sg.cells[index] = self.random[self.value_index[self.i]];
 
             while (sg.cell_is_problematic(self.sudoku_coords, index)
                 || self
-                    .other_sudoku_coords
-                    .is_some_and(|s| sg.cell_is_problematic(s, index)))
-                && self.value_index[self.i] < 8
+                    .other_sudokus
+                    .iter()
+                    .any(|s| sg.cell_is_problematic(*s, index)))
+                && self.value_index[self.i] < last_value
             {
                 self.value_index[self.i] += 1;
                 sg.cells[index] = self.random[self.value_index[self.i]];
@@ -204,8 +461,9 @@ impl<'a> DfsBlock<'a> {
 
             if sg.cell_is_problematic(self.sudoku_coords, index)
                 || self
-                    .other_sudoku_coords
-                    .is_some_and(|s| sg.cell_is_problematic(s, index))
+                    .other_sudokus
+                    .iter()
+                    .any(|s| sg.cell_is_problematic(*s, index))
             {
                 // there is no solution, we should backtrack
                 self.backtrack(sg)?;
@@ -221,7 +479,7 @@ impl<'a> DfsBlock<'a> {
     pub fn reset(&mut self, sg: &mut SudokuGrid) {
         self.i = 0;
         self.backtracks = 0;
-        self.value_index = [0; 9];
+        self.value_index.fill(0);
         for i in self.indexes.iter() {
             sg.cells[*i] = 0;
         }
@@ -230,109 +488,269 @@ impl<'a> DfsBlock<'a> {
 
 impl SudokuGrid {
     pub fn new(n: usize, m: usize) -> Self {
-        let new_sudoku = |x: usize, y: usize| -> Sudoku {
-            let this_start = (x + y * n) * 7 * 9;
+        SudokuGrid::with_wrap(n, m, Wrap::Torus)
+    }
+
+    /// Create an n by m world with edges connected according to `wrap`
+    pub fn with_wrap(n: usize, m: usize, wrap: Wrap) -> Self {
+        SudokuGrid::with_topology(GridTopology::new(n, m, wrap))
+    }
+
+    pub fn with_topology(topology: impl Topology + 'static) -> Self {
+        SudokuGrid::with_layout(Rc::new(Layout::new(topology)))
+    }
+
+    /// Create an empty grid, with the same sudokus as another grid using this layout
+    pub fn with_layout(layout: Rc<Layout>) -> Self {
+        let boxes = layout.boxes();
+        let new_sudoku = |(x, y): Coords| -> Sudoku {
+            let blocks = 0..boxes.values();
             Sudoku {
-                block_start: [
-                    (x + ((y + 1) % m) * n) * 7 * 9 + 6 * 9,
-                    this_start,
-                    (((x + 1) % n) + y * n) * 7 * 9 + 4 * 9,
-                    this_start + 9,
-                    this_start + 2 * 9,
-                    this_start + 3 * 9,
-                    this_start + 4 * 9,
-                    this_start + 5 * 9,
-                    this_start + 6 * 9,
-                ],
+                block_start: blocks
+                    .clone()
+                    .map(|block| layout.block_start((x, y), block))
+                    .collect(),
+                mirrored: blocks
+                    .map(|block| layout.is_mirrored((x, y), block))
+                    .collect(),
+                boxes,
                 x,
                 y,
             }
         };
 
         SudokuGrid {
-            cells: vec![0; 7 * 9 * n * m].into(),
-            sudokus: (0..n * m).map(|i| new_sudoku(i % n, i / n)).collect(),
-            n,
-            m,
+            cells: vec![0; layout.len()].into(),
+            sudokus: layout.sudoku_coords().map(new_sudoku).collect(),
+            n: layout.n,
+            m: layout.m,
+            layout,
+            rules: Rules::default(),
+            extra_regions: Rc::new([]),
+            window_cells: Rc::new([]),
+            cages: Rc::new([]),
+            cage_indexes: Rc::new([]),
+            chess_peers: Rc::new([]),
         }
     }
 
-    pub fn sudoku(&self, coords: Coords) -> &Sudoku {
-        debug_assert!(coords.0 < self.n && coords.1 < self.m);
-        &self.sudokus[coords.0 + coords.1 * self.n]
+    /// Create an empty grid with the same layout, rules, and cages as this one
+    pub fn empty_like(&self) -> Self {
+        SudokuGrid {
+            rules: self.rules,
+            extra_regions: self.extra_regions.clone(),
+            window_cells: self.window_cells.clone(),
+            cages: self.cages.clone(),
+            cage_indexes: self.cage_indexes.clone(),
+            chess_peers: self.chess_peers.clone(),
+            ..SudokuGrid::with_layout(self.layout())
+        }
     }
 
-    /// Loop over sudoku rows (for export to send to WebGL)
-    /// TODO: update WebGL shader to use the original cell data format directly?
-    pub fn sudoku_rows(&self) -> impl Iterator<Item = u8> + use<'_> {
-        let x = 0..self.n;
-        let y = (0..self.m).rev();
-        y.flat_map(move |y| x.clone().map(move |x| self.sudoku((x, y))))
-            .flat_map(move |s| (0..9).flat_map(move |y| self.row(s, y).values().copied()))
+    /// Add variant rules to every sudoku of the grid
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self.extra_regions = self.sudoku_regions();
+        self.window_cells = self.window_cells();
+        self.chess_peers = self.chess_moves();
+        self
     }
 
-    pub fn block(&self, sudoku: &Sudoku, i: usize) -> Region<'_, impl Iterator<Item = usize>> {
-        debug_assert!(i < 9, "block index out of bounds");
-        let start = sudoku.block_start[i];
-        Region(self, start..(start + 9))
+    /// Add killer cages to the grid, checking that every cage has cells, all in the grid and
+    /// in no other cage
+    pub fn with_cages(mut self, cages: Vec<Cage>) -> Result<Self, CageError> {
+        let mut cage_indexes = vec![None; self.cells.len()];
+        for (k, cage) in cages.iter().enumerate() {
+            if cage.cells.is_empty() {
+                return Err(CageError::Empty);
+            }
+            for &i in cage.cells.iter() {
+                let index = cage_indexes.get_mut(i).ok_or(CageError::OutOfBounds(i))?;
+                if index.is_some() {
+                    return Err(CageError::Overlapping(i));
+                }
+                *index = Some(k);
+            }
+        }
+        self.cages = cages.into();
+        self.cage_indexes = if self.cages.is_empty() {
+            Rc::new([])
+        } else {
+            cage_indexes.into()
+        };
+        Ok(self)
     }
 
-    pub fn row(&self, sudoku: &Sudoku, y: usize) -> Region<'_, impl Iterator<Item = usize>> {
-        debug_assert!(y < 9, "row index out of bounds");
-        let first_block = (y / 3) * 3;
-        let blocks = [
-            sudoku.block_start[first_block],
-            sudoku.block_start[first_block + 1],
-            sudoku.block_start[first_block + 2],
-        ];
-        let first_offset = (y % 3) * 3;
-        let offsets = [first_offset, first_offset + 1, first_offset + 2];
+    pub fn layout(&self) -> Rc<Layout> {
+        self.layout.clone()
+    }
+
+    /// Size of the blocks of every sudoku
+    pub fn boxes(&self) -> BoxSize {
+        self.layout.boxes()
+    }
+
+    /// Variant rules that hold in every sudoku
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Killer cages of the grid (empty if it is not a killer sudoku)
+    pub fn cages(&self) -> &[Cage] {
+        &self.cages
+    }
+
+    /// Get the cage the cell at index i is part of, if any
+    pub fn cage_for(&self, i: usize) -> Option<&Cage> {
+        let cage = (*self.cage_indexes.get(i)?)?;
+        Some(&self.cages[cage])
+    }
+
+    /// Get the regions the rules add to a sudoku, as lists of indexes (see
+    /// [`SudokuGrid::region`])
+    pub fn extra_regions(&self, sudoku: &Sudoku) -> &[(RegionKind, Box<[usize]>)] {
+        let regions = self.layout.sudoku_index(sudoku.coords());
+        regions
+            .and_then(|s| self.extra_regions.get(s))
+            .map_or(&[], |regions| regions)
+    }
+
+    /// Check if the cell at index i is part of a window of any sudoku (see
+    /// [`Rules::windows`])
+    pub fn in_window(&self, i: usize) -> bool {
+        self.window_cells.get(i).copied().unwrap_or(false)
+    }
+
+    /// Get the cells a knight's or king's move away from the cell at index i, with the rule
+    /// they break when they hold the same value (empty without chess rules)
+    pub fn chess_peers(&self, i: usize) -> &[(RegionKind, usize)] {
+        self.chess_peers.get(i).map_or(&[], |peers| peers)
+    }
+
+    /// Get the value of the cell at index i (without its flags)
+    pub fn value(&self, i: usize) -> u8 {
+        self.cells[i] & self.boxes().value_mask()
+    }
+
+    /// How the edges are connected (`None` for topologies that are not an n by m world)
+    pub fn wrap(&self) -> Option<Wrap> {
+        self.layout.topology().wrap()
+    }
+
+    /// The preset this grid was made from, if any
+    pub fn preset(&self) -> Option<Preset> {
+        self.layout.topology().preset()
+    }
+
+    /// What neighbours share, for edge tilings
+    pub fn edge_overlap(&self) -> Option<EdgeOverlap> {
+        self.layout.topology().edge_overlap()
+    }
+
+    /// Whether there is a sudoku at these coordinates
+    pub fn has_sudoku(&self, coords: Coords) -> bool {
+        self.layout.contains(coords)
+    }
+
+    /// Get the coordinates of every sudoku, ordered by `x + y * n`
+    pub fn sudoku_coords(&self) -> impl Iterator<Item = Coords> + use<'_> {
+        self.sudokus.iter().map(|s| s.coords())
+    }
+
+    pub fn sudoku(&self, coords: Coords) -> &Sudoku {
+        let i = self.layout.sudoku_index(coords);
+        &self.sudokus[i.unwrap_or_else(|| panic!("There is no sudoku at {coords:?}"))]
+    }
+
+    pub fn block(&self, sudoku: &Sudoku, i: usize) -> Region<'_, impl Iterator<Item = usize>> {
+        let boxes = self.boxes();
+        debug_assert!(i < boxes.values(), "block index out of bounds");
+        let (start, mirrored) = (sudoku.block_start[i], sudoku.mirrored[i]);
         Region(
             self,
-            blocks
-                .into_iter()
-                .flat_map(move |b| offsets.into_iter().map(move |o| o + b)),
+            (0..boxes.values()).map(move |o| start + boxes.stored_offset(mirrored, o)),
+        )
+    }
+
+    pub fn row(&self, sudoku: &Sudoku, y: usize) -> Region<'_, impl Iterator<Item = usize>> {
+        let boxes = self.boxes();
+        debug_assert!(y < boxes.values(), "row index out of bounds");
+        let first_block = (y / boxes.height) * boxes.across();
+        let first_offset = (y % boxes.height) * boxes.width;
+        self.line(
+            sudoku,
+            (first_block, 1, boxes.across()),
+            (first_offset, 1, boxes.width),
         )
     }
 
     pub fn column(&self, sudoku: &Sudoku, x: usize) -> Region<'_, impl Iterator<Item = usize>> {
-        debug_assert!(x < 9, "column index out of bounds");
-        let first_block = x / 3;
-        let blocks = [
-            sudoku.block_start[first_block],
-            sudoku.block_start[first_block + 3],
-            sudoku.block_start[first_block + 6],
-        ];
-        let first_offset = x % 3;
-        let offsets = [first_offset, first_offset + 3, first_offset + 6];
+        let boxes = self.boxes();
+        debug_assert!(x < boxes.values(), "column index out of bounds");
+        self.line(
+            sudoku,
+            (x / boxes.width, boxes.across(), boxes.down()),
+            (x % boxes.width, boxes.width, boxes.height),
+        )
+    }
+
+    /// Row or column going through several blocks, with the blocks and the offsets in every
+    /// block given as (first, step, count)
+    fn line(
+        &self,
+        sudoku: &Sudoku,
+        (first_block, block_step, blocks): (usize, usize, usize),
+        (first_offset, offset_step, offsets): (usize, usize, usize),
+    ) -> Region<'_, impl Iterator<Item = usize>> {
+        let boxes = self.boxes();
+        let mut starts = [(0, false); MAX_LINE_BLOCKS];
+        for (k, start) in starts.iter_mut().take(blocks).enumerate() {
+            let b = first_block + k * block_step;
+            *start = (sudoku.block_start[b], sudoku.mirrored[b]);
+        }
         Region(
             self,
-            blocks
+            starts
                 .into_iter()
-                .flat_map(move |b| offsets.into_iter().map(move |o| o + b)),
+                .take(blocks)
+                .flat_map(move |(b, mirrored)| {
+                    (0..offsets).map(move |o| {
+                        b + boxes.stored_offset(mirrored, first_offset + o * offset_step)
+                    })
+                }),
         )
     }
 
     pub fn block_index_for(&self, sudoku: &Sudoku, i: usize) -> usize {
+        let values = self.boxes().values();
         for (block, start) in sudoku.block_start.iter().enumerate() {
-            if i >= *start && i < start + 9 {
+            if i >= *start && i < start + values {
                 return block;
             }
         }
         panic!("Index {i} not found in sudoku {sudoku:?}");
     }
 
+    /// Get all block indexes of the sudoku that contain the cell index
+    pub fn block_indexes_for<'a>(
+        &self,
+        sudoku: &'a Sudoku,
+        i: usize,
+    ) -> impl Iterator<Item = usize> + use<'a> {
+        let values = self.boxes().values();
+        (0..values)
+            .filter(move |b| i >= sudoku.block_start[*b] && i < sudoku.block_start[*b] + values)
+    }
+
     /// Get row for cell index
     pub fn row_for(&self, sudoku: &Sudoku, i: usize) -> Region<'_, impl Iterator<Item = usize>> {
-        let block = self.block_index_for(sudoku, i);
-        let row = (i % 9) / 3 + block / 3 * 3;
+        let (row, _) = sudoku.position(self.block_index_for(sudoku, i), i);
         self.row(sudoku, row)
     }
 
     /// Get column for cell index
     pub fn column_for(&self, sudoku: &Sudoku, i: usize) -> Region<'_, impl Iterator<Item = usize>> {
-        let block = self.block_index_for(sudoku, i);
-        let column = i % 3 + (block % 3) * 3;
+        let (_, column) = sudoku.position(self.block_index_for(sudoku, i), i);
         self.column(sudoku, column)
     }
 
@@ -341,6 +759,14 @@ impl SudokuGrid {
         self.block(sudoku, self.block_index_for(sudoku, i))
     }
 
+    /// Get the region made up of a list of indexes
+    pub fn region<'a>(
+        &'a self,
+        indexes: &'a [usize],
+    ) -> Region<'a, impl Iterator<Item = usize> + 'a> {
+        Region(self, indexes.iter().copied())
+    }
+
     pub fn set_block(&mut self, sudoku: &Sudoku, i: usize, values: impl Iterator<Item = u8>) {
         for (i, v) in self.block(sudoku, i).indexes().zip(values) {
             self.cells[i] = v;
@@ -349,68 +775,198 @@ impl SudokuGrid {
 
     /// Check if sudoku is solved correctly
     pub fn is_solved(&self, sudoku: &Sudoku) -> bool {
+        let values = self.boxes().values();
+
         // row constraint
-        if !(0..9).all(|i| self.row(sudoku, i).validate(false)) {
+        if !(0..values).all(|i| self.row(sudoku, i).validate(false)) {
             return false;
         }
 
         // column constraint
-        if !(0..9).all(|i| self.column(sudoku, i).validate(false)) {
+        if !(0..values).all(|i| self.column(sudoku, i).validate(false)) {
             return false;
         }
 
         // block constraint
-        if !(0..9).all(|i| self.block(sudoku, i).validate(false)) {
+        if !(0..values).all(|i| self.block(sudoku, i).validate(false)) {
             return false;
         }
 
-        true
+        // constraints of the variant rules
+        if !self
+            .extra_regions(sudoku)
+            .iter()
+            .all(|(_, indexes)| self.region(indexes).validate(false))
+        {
+            return false;
+        }
+
+        // chess moves can reach into the sudokus next to this one
+        !sudoku.indexes().any(|i| self.chess_is_problematic(i))
     }
 
     pub fn is_solved_all(&self) -> bool {
-        for x in 0..self.n {
-            for y in 0..self.m {
-                if !self.is_solved(self.sudoku((x, y))) {
-                    return false;
-                }
-            }
-        }
-        true
+        self.sudokus.iter().all(|s| self.is_solved(s)) && self.cages_are_solved()
     }
 
     /// Check if the cell at index i is problematic
     pub fn cell_is_problematic(&self, sudoku_coords: Coords, i: usize) -> bool {
-        let value = self.cells[i] & 15;
+        let value = self.value(i);
         let sudoku = self.sudoku(sudoku_coords);
         self.row_for(sudoku, i).has_duplicate_value(value)
             || self.column_for(sudoku, i).has_duplicate_value(value)
             || self.block_for(sudoku, i).has_duplicate_value(value)
+            || self.extra_regions(sudoku).iter().any(|(_, indexes)| {
+                indexes.contains(&i) && self.region(indexes).has_duplicate_value(value)
+            })
+            || self.cage_is_problematic(i)
+            || self.chess_is_problematic(i)
     }
 
+    /// Get the sudoku that stores the cell at index i
     pub fn sudoku_at_index(&self, i: usize) -> Coords {
-        let sudoku_i = i / 9 / 7;
-        let x = sudoku_i % self.n;
-        let y = sudoku_i / self.n;
-        (x, y)
+        self.sudokus_at_index(i)[0]
     }
 
-    pub fn sudokus_at_index(&self, i: usize) -> (Coords, Option<Coords>) {
-        let block_i = i / 9;
-        let block = match block_i % 7 {
-            0 => TOP_CENTER_BLOCK,
-            n => n + 2, // add to because top left and top right are skipped
-        };
-        let sudoku_i = block_i / 7;
-        let x = sudoku_i % self.n;
-        let y = sudoku_i / self.n;
-        let other_coords = match block {
-            TOP_LEFT_BLOCK => Some((x, (y + 1) % self.m)),
-            TOP_RIGHT_BLOCK => Some(((x + 1) % self.n, y)),
-            BOTTOM_LEFT_BLOCK => Some(((x + self.n - 1) % self.n, y)),
-            BOTTOM_RIGHT_BLOCK => Some((x, (y + self.m - 1) % self.m)),
-            _ => None,
-        };
-        ((x, y), other_coords)
+    /// Get every sudoku the cell at index i is part of, starting with the one storing it
+    pub fn sudokus_at_index(&self, i: usize) -> Vec<Coords> {
+        let mut sudokus: Vec<Coords> = Vec::new();
+        for p in self.layout.positions(i) {
+            if !sudokus.contains(&p.sudoku) {
+                sudokus.push(p.sudoku);
+            }
+        }
+        sudokus
+    }
+
+    /// Get the other sudokus that block i of a sudoku is shared with
+    pub fn shared_with(&self, coords: Coords, i: usize) -> Vec<Coords> {
+        self.layout.shared_with(coords, i)
+    }
+
+    /// Get all other cells sharing a row, column, block, or extra region with the cell at
+    /// index i (in any of the sudokus it is part of), its cage, or a chess move away from
+    /// it, sorted and without duplicates
+    pub fn peers(&self, i: usize) -> Vec<usize> {
+        let mut peers = Vec::new();
+        for sudoku in self.sudokus_at_index(i).into_iter().map(|s| self.sudoku(s)) {
+            // in worlds that are 1 sudoku wide or high, a block can be in a sudoku twice
+            for block in self.block_indexes_for(sudoku, i) {
+                let (row, column) = sudoku.position(block, i);
+                peers.extend(self.row(sudoku, row).indexes());
+                peers.extend(self.column(sudoku, column).indexes());
+                peers.extend(self.block(sudoku, block).indexes());
+            }
+            for (_, indexes) in self.extra_regions(sudoku) {
+                if indexes.contains(&i) {
+                    peers.extend(indexes.iter());
+                }
+            }
+        }
+        if let Some(cage) = self.cage_for(i) {
+            peers.extend(cage.cells.iter());
+        }
+        peers.extend(self.chess_peers(i).iter().map(|(_, p)| p));
+        peers.sort_unstable();
+        peers.dedup();
+        peers.retain(|p| *p != i);
+        peers
+    }
+
+    /// Get every cell clashing with the value in the cell at index i, with the region and
+    /// sudoku they clash in (a clash in a shared block is only listed for the first sudoku)
+    pub fn conflicts(&self, i: usize) -> Vec<Conflict> {
+        let value = self.value(i);
+        if value == 0 {
+            return Vec::new();
+        }
+
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for sudoku in self.sudokus_at_index(i).into_iter().map(|s| self.sudoku(s)) {
+            for block in self.block_indexes_for(sudoku, i) {
+                let (row, column) = sudoku.position(block, i);
+                let mut regions = vec![
+                    (
+                        RegionKind::Row,
+                        self.row(sudoku, row).indexes().collect::<Box<[_]>>(),
+                    ),
+                    (
+                        RegionKind::Column,
+                        self.column(sudoku, column).indexes().collect(),
+                    ),
+                    (
+                        RegionKind::Block,
+                        self.block(sudoku, block).indexes().collect(),
+                    ),
+                ];
+                regions.extend(
+                    self.extra_regions(sudoku)
+                        .iter()
+                        .filter(|(_, indexes)| indexes.contains(&i))
+                        .cloned(),
+                );
+                for (region, indexes) in regions {
+                    for index in indexes {
+                        let known = conflicts
+                            .iter()
+                            .any(|c| c.index == index && c.region == region);
+                        if index != i && self.value(index) == value && !known {
+                            conflicts.push(Conflict {
+                                index,
+                                region,
+                                sudoku: sudoku.coords(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(cage) = self.cage_for(i) {
+            // cages can span sudokus, so their clashes are listed for the sudoku storing i
+            let sudoku = self.sudoku_at_index(i);
+            for index in cage.cells.iter().copied() {
+                if index != i && self.value(index) == value {
+                    conflicts.push(Conflict {
+                        index,
+                        region: RegionKind::Cage,
+                        sudoku,
+                    });
+                }
+            }
+        }
+        for (region, index) in self.chess_peers(i).iter().copied() {
+            // chess moves are made in the world, so like cages they can cross into other
+            // sudokus
+            if self.value(index) == value {
+                conflicts.push(Conflict {
+                    index,
+                    region,
+                    sudoku: self.sudoku_at_index(i),
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// Get all cells in the world containing value
+    pub fn cells_with_value(&self, value: u8) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|i| value > 0 && self.value(*i) == value)
+            .collect()
+    }
+
+    /// Check if value can be entered in the cell at index i without clashing with a peer
+    pub fn is_candidate(&self, i: usize, value: u8) -> bool {
+        self.peers(i).into_iter().all(|p| self.value(p) != value)
+    }
+
+    /// Get all empty cells in the world where value is still a candidate (and still leaves a
+    /// way to complete their cage)
+    pub fn candidate_cells(&self, value: u8) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|i| value > 0 && self.value(*i) == 0 && self.is_candidate(*i, value))
+            .filter(|i| self.cage_allows(*i, value))
+            .collect()
     }
 
     /// Solve a sudoku with depth-first search
@@ -422,6 +978,7 @@ impl SudokuGrid {
         let mut i = 0;
         let mut ignore_non_zero = true;
         let mut backtracks: u64 = 0;
+        let values = self.boxes().values() as u8;
 
         let indexes = self
             .sudoku(sudoku_coords)
@@ -438,7 +995,8 @@ impl SudokuGrid {
 
             // we will guess a value for this index
             self.cells[indexes[i]] += 1;
-            while self.cells[indexes[i]] < 9 && self.cell_is_problematic(sudoku_coords, indexes[i])
+            while self.cells[indexes[i]] < values
+                && self.cell_is_problematic(sudoku_coords, indexes[i])
             {
                 self.cells[indexes[i]] += 1
             }
@@ -446,7 +1004,7 @@ impl SudokuGrid {
             if self.cell_is_problematic(sudoku_coords, indexes[i]) {
                 // there is no solution, we should backtrack
                 backtracks += 1;
-                while self.cells[indexes[i]] == 9 {
+                while self.cells[indexes[i]] == values {
                     self.cells[indexes[i]] = 0;
                     i = guesses.pop().ok_or(NoSolution)?;
                     ignore_non_zero = false;
@@ -464,12 +1022,16 @@ impl SudokuGrid {
 
     pub fn solve_trivial_regions(&mut self, sudoku_coords: Coords) -> bool {
         let sudoku = self.sudoku(sudoku_coords);
+        let values = self.boxes().values() as u8;
         let mut unsolved_regions = Vec::<Vec<usize>>::new();
-        for i in 0..9 {
+        for i in 0..values as usize {
             unsolved_regions.push(self.row(sudoku, i).indexes().collect());
             unsolved_regions.push(self.column(sudoku, i).indexes().collect());
             unsolved_regions.push(self.block(sudoku, i).indexes().collect());
         }
+        for (_, indexes) in self.extra_regions(sudoku) {
+            unsolved_regions.push(indexes.to_vec());
+        }
 
         let mut has_changed = true;
         let mut has_changed_at_all = false;
@@ -490,7 +1052,7 @@ impl SudokuGrid {
                 if empties == 1 {
                     // get last missing value in region
                     let mut missing = 0;
-                    for v in 1..=9 {
+                    for v in 1..=values {
                         if !seen.contains(v) {
                             missing = v;
                             break;
@@ -514,52 +1076,77 @@ impl SudokuGrid {
     }
 
     pub fn mark_errors(&mut self) {
+        let error = self.boxes().error_flag();
         for i in 0..self.cells.len() {
-            let (s1, s2) = self.sudokus_at_index(i);
-
-            if self.cells[i] & 15 == 0 {
-                self.cells[i] &= !32; // not error
+            if self.value(i) == 0 {
+                self.cells[i] &= !error;
                 continue;
             }
 
-            if self.cell_is_problematic(s1, i)
-                || s2.is_some_and(|s2| self.cell_is_problematic(s2, i))
-            {
-                self.cells[i] |= 32; // error
+            let sudokus = self.sudokus_at_index(i);
+            if sudokus.into_iter().any(|s| self.cell_is_problematic(s, i)) {
+                self.cells[i] |= error;
+            } else {
+                self.cells[i] &= !error;
+            }
+        }
+    }
+
+    /// Mark user entries that differ from the solution as wrong (bit 64, see
+    /// [`BoxSize::wrong_flag`]), independent of the errors marked by `mark_errors`
+    pub fn mark_wrong(&mut self, solution: &[u8]) -> Result<(), SolutionLength> {
+        if solution.len() != self.cells.len() {
+            return Err(SolutionLength {
+                expected: self.cells.len(),
+                actual: solution.len(),
+            });
+        }
+        let boxes = self.boxes();
+        let mask = boxes.value_mask();
+        for (cell, solved) in self.cells.iter_mut().zip(solution) {
+            let value = *cell & mask;
+            if *cell & boxes.user_flag() > 0 && value != 0 && value != solved & mask {
+                *cell |= boxes.wrong_flag();
             } else {
-                self.cells[i] &= !32; // not error
+                *cell &= !boxes.wrong_flag();
             }
         }
+        Ok(())
     }
 }
 
 impl std::fmt::Debug for SudokuGrid {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for x in 0..self.n {
-            for y in 0..self.m {
-                let s = self.sudoku((x, y));
-                writeln!(f, "Sudoku ({x}, {y}):")?;
-                writeln!(f, "┌───────┬───────┬───────┐")?;
-                for i in 0..9 {
-                    write!(f, "│ ")?;
-                    for (j, v) in self.row(s, i).values().enumerate() {
-                        let spaces = if j == 8 {
-                            " │"
-                        } else if j % 3 == 2 {
-                            " │ "
-                        } else {
-                            " "
-                        };
-                        write!(f, "{}{}", v, spaces)?;
-                    }
-                    if i % 3 == 2 && i < 8 {
-                        writeln!(f, "\n├───────┼───────┼───────┤")?;
+        let boxes = self.boxes();
+        let values = boxes.values();
+        let line = |left: &str, middle: &str, right: &str| {
+            let segment = "─".repeat(2 * boxes.width + 1);
+            let segments = vec![segment; boxes.across()];
+            format!("{left}{}{right}", segments.join(middle))
+        };
+        for s in self.sudokus.iter() {
+            let (x, y) = s.coords();
+            writeln!(f, "Sudoku ({x}, {y}):")?;
+            writeln!(f, "{}", line("┌", "┬", "┐"))?;
+            for i in 0..values {
+                write!(f, "│ ")?;
+                for (j, v) in self.row(s, i).values().enumerate() {
+                    let spaces = if j == values - 1 {
+                        " │"
+                    } else if j % boxes.width == boxes.width - 1 {
+                        " │ "
                     } else {
-                        writeln!(f)?;
-                    }
+                        " "
+                    };
+                    write!(f, "{}{}", v, spaces)?;
+                }
+                if i % boxes.height == boxes.height - 1 && i < values - 1 {
+                    writeln!(f, "\n{}", line("├", "┼", "┤"))?;
+                } else {
+                    writeln!(f)?;
                 }
-                writeln!(f, "└───────┴───────┴───────┘")?;
             }
+            writeln!(f, "{}", line("└", "┴", "┘"))?;
         }
         Ok(())
     }
@@ -568,6 +1155,7 @@ impl std::fmt::Debug for SudokuGrid {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::topology::EdgeTopology;
 
     #[test]
     fn grid_overlap_1x1() {
@@ -641,63 +1229,63 @@ mod tests {
 
         // blocks
         assert_eq!(
-            sg.block(&s, TOP_LEFT_BLOCK)
+            sg.block(s, TOP_LEFT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [71, 72, 73, 74, 75, 76, 77, 78, 79]
         );
         assert_eq!(
-            sg.block(&s, TOP_CENTER_BLOCK)
+            sg.block(s, TOP_CENTER_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [11, 12, 13, 14, 15, 16, 17, 18, 19]
         );
         assert_eq!(
-            sg.block(&s, TOP_RIGHT_BLOCK)
+            sg.block(s, TOP_RIGHT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [51, 52, 53, 54, 55, 56, 57, 58, 59]
         );
         assert_eq!(
-            sg.block(&s, MIDDLE_LEFT_BLOCK)
+            sg.block(s, MIDDLE_LEFT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [21, 22, 23, 24, 25, 26, 27, 28, 29]
         );
         assert_eq!(
-            sg.block(&s, MIDDLE_CENTER_BLOCK)
+            sg.block(s, MIDDLE_CENTER_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [31, 32, 33, 34, 35, 36, 37, 38, 39]
         );
         assert_eq!(
-            sg.block(&s, MIDDLE_RIGHT_BLOCK)
+            sg.block(s, MIDDLE_RIGHT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [41, 42, 43, 44, 45, 46, 47, 48, 49]
         );
         assert_eq!(
-            sg.block(&s, BOTTOM_LEFT_BLOCK)
+            sg.block(s, BOTTOM_LEFT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [51, 52, 53, 54, 55, 56, 57, 58, 59]
         );
         assert_eq!(
-            sg.block(&s, BOTTOM_CENTER_BLOCK)
+            sg.block(s, BOTTOM_CENTER_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
             [61, 62, 63, 64, 65, 66, 67, 68, 69]
         );
         assert_eq!(
-            sg.block(&s, BOTTOM_RIGHT_BLOCK)
+            sg.block(s, BOTTOM_RIGHT_BLOCK)
                 .values()
                 .copied()
                 .collect::<Vec<_>>(),
@@ -706,77 +1294,77 @@ mod tests {
 
         // rows
         assert_eq!(
-            sg.row(&s, 0).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 0).values().copied().collect::<Vec<_>>(),
             [71, 72, 73, 11, 12, 13, 51, 52, 53]
         );
         assert_eq!(
-            sg.row(&s, 1).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 1).values().copied().collect::<Vec<_>>(),
             [74, 75, 76, 14, 15, 16, 54, 55, 56]
         );
         assert_eq!(
-            sg.row(&s, 2).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 2).values().copied().collect::<Vec<_>>(),
             [77, 78, 79, 17, 18, 19, 57, 58, 59]
         );
         assert_eq!(
-            sg.row(&s, 3).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 3).values().copied().collect::<Vec<_>>(),
             [21, 22, 23, 31, 32, 33, 41, 42, 43]
         );
         assert_eq!(
-            sg.row(&s, 4).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 4).values().copied().collect::<Vec<_>>(),
             [24, 25, 26, 34, 35, 36, 44, 45, 46]
         );
         assert_eq!(
-            sg.row(&s, 5).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 5).values().copied().collect::<Vec<_>>(),
             [27, 28, 29, 37, 38, 39, 47, 48, 49]
         );
         assert_eq!(
-            sg.row(&s, 6).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 6).values().copied().collect::<Vec<_>>(),
             [51, 52, 53, 61, 62, 63, 71, 72, 73]
         );
         assert_eq!(
-            sg.row(&s, 7).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 7).values().copied().collect::<Vec<_>>(),
             [54, 55, 56, 64, 65, 66, 74, 75, 76]
         );
         assert_eq!(
-            sg.row(&s, 8).values().copied().collect::<Vec<_>>(),
+            sg.row(s, 8).values().copied().collect::<Vec<_>>(),
             [57, 58, 59, 67, 68, 69, 77, 78, 79]
         );
 
         // columns
         assert_eq!(
-            sg.column(&s, 0).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 0).values().copied().collect::<Vec<_>>(),
             [71, 74, 77, 21, 24, 27, 51, 54, 57]
         );
         assert_eq!(
-            sg.column(&s, 1).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 1).values().copied().collect::<Vec<_>>(),
             [72, 75, 78, 22, 25, 28, 52, 55, 58]
         );
         assert_eq!(
-            sg.column(&s, 2).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 2).values().copied().collect::<Vec<_>>(),
             [73, 76, 79, 23, 26, 29, 53, 56, 59]
         );
         assert_eq!(
-            sg.column(&s, 3).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 3).values().copied().collect::<Vec<_>>(),
             [11, 14, 17, 31, 34, 37, 61, 64, 67]
         );
         assert_eq!(
-            sg.column(&s, 4).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 4).values().copied().collect::<Vec<_>>(),
             [12, 15, 18, 32, 35, 38, 62, 65, 68]
         );
         assert_eq!(
-            sg.column(&s, 5).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 5).values().copied().collect::<Vec<_>>(),
             [13, 16, 19, 33, 36, 39, 63, 66, 69]
         );
         assert_eq!(
-            sg.column(&s, 6).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 6).values().copied().collect::<Vec<_>>(),
             [51, 54, 57, 41, 44, 47, 71, 74, 77]
         );
         assert_eq!(
-            sg.column(&s, 7).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 7).values().copied().collect::<Vec<_>>(),
             [52, 55, 58, 42, 45, 48, 72, 75, 78]
         );
         assert_eq!(
-            sg.column(&s, 8).values().copied().collect::<Vec<_>>(),
+            sg.column(s, 8).values().copied().collect::<Vec<_>>(),
             [53, 56, 59, 43, 46, 49, 73, 76, 79]
         );
     }
@@ -790,7 +1378,7 @@ mod tests {
                 let start = sg.sudoku((x, y)).block_start[TOP_CENTER_BLOCK];
                 for i in 0..9 * 7 {
                     assert_eq!(sg.sudoku_at_index(start + i), (x, y));
-                    assert_eq!(sg.sudokus_at_index(start + i).0, (x, y));
+                    assert_eq!(sg.sudokus_at_index(start + i)[0], (x, y));
                     let _ = sg.block_index_for(sg.sudoku((x, y)), start + i); // shouldn't panic
                 }
 
@@ -800,13 +1388,13 @@ mod tests {
                     sg.sudoku((x, (y + 1) % 3)).block_start[BOTTOM_RIGHT_BLOCK]
                 );
                 for i in 0..9 {
-                    assert_eq!(sg.sudokus_at_index(start + i).1, Some((x, y)));
+                    assert_eq!(sg.sudokus_at_index(start + i)[1], (x, y));
                     let _ = sg.block_index_for(sg.sudoku((x, y)), start + i); // shouldn't panic
                 }
 
                 let start = sg.sudoku((x, y)).block_start[TOP_RIGHT_BLOCK];
                 for i in 0..9 {
-                    assert_eq!(sg.sudokus_at_index(start + i).1, Some((x, y)));
+                    assert_eq!(sg.sudokus_at_index(start + i)[1], (x, y));
                     let _ = sg.block_index_for(sg.sudoku((x, y)), start + i); // shouldn't panic
                 }
             }
@@ -888,18 +1476,18 @@ mod tests {
         sg.cells[0] = 9;
         sg.cells[1] = 9;
 
-        assert_eq!(sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7), false);
-        assert_eq!(sg.row(&s, 0).has_duplicate_value(7), false);
+        assert!(!sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7));
+        assert!(!sg.row(&s, 0).has_duplicate_value(7));
         sg.cells[0] = 7;
-        assert_eq!(sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7), false);
-        assert_eq!(sg.row(&s, 0).has_duplicate_value(7), false);
+        assert!(!sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7));
+        assert!(!sg.row(&s, 0).has_duplicate_value(7));
         sg.cells[2] = 7;
-        assert_eq!(sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7), true);
-        assert_eq!(sg.row(&s, 0).has_duplicate_value(7), true);
+        assert!(sg.block(&s, TOP_CENTER_BLOCK).has_duplicate_value(7));
+        assert!(sg.row(&s, 0).has_duplicate_value(7));
 
-        assert_eq!(sg.column(&s, 3).has_duplicate_value(7), false);
+        assert!(!sg.column(&s, 3).has_duplicate_value(7));
         sg.cells[s.block_start[MIDDLE_CENTER_BLOCK]] = 7;
-        assert_eq!(sg.column(&s, 3).has_duplicate_value(7), true);
+        assert!(sg.column(&s, 3).has_duplicate_value(7));
     }
 
     #[test]
@@ -940,4 +1528,186 @@ mod tests {
         sg.mark_errors(); // should remain stable
         assert_eq!(sg.cells, expected);
     }
+
+    #[test]
+    fn mark_errors_in_four_sudokus() {
+        let mut sg = SudokuGrid::with_topology(EdgeTopology::new(2, 2, EdgeOverlap::Band));
+        let layout = sg.layout();
+        // the center of the world is the bottom right block of (0, 0), and part of all four
+        let center = layout.index((0, 0), 6, 6);
+        assert_eq!(sg.sudokus_at_index(center).len(), 4);
+        assert_eq!(layout.index((1, 1), 0, 0), center);
+
+        // clashes with a cell in a row of (1, 1) that is not part of (0, 0)
+        let other = layout.index((1, 1), 0, 8);
+        sg.cells[center] = 5;
+        sg.cells[other] = 5 + 16;
+        sg.mark_errors();
+        assert_eq!((sg.cells[center], sg.cells[other]), (5 + 32, 5 + 16 + 32));
+
+        sg.cells[other] = 6 + 16;
+        sg.mark_errors();
+        assert_eq!((sg.cells[center], sg.cells[other]), (5, 6 + 16));
+    }
+
+    #[test]
+    fn highlight_queries() {
+        let mut sg = SudokuGrid::new(2, 2);
+        let layout = sg.layout();
+        let center = layout.index((0, 0), 4, 4);
+        assert_eq!(sg.peers(center).len(), 20);
+        // a cell in a shared block sees the rows and columns of both sudokus
+        let corner = layout.index((0, 0), 8, 8);
+        assert_eq!(sg.peers(corner).len(), 32);
+
+        sg.cells[center] = 5;
+        let other = layout.index((1, 1), 0, 4);
+        sg.cells[other] = 5 + 16;
+        assert_eq!(sg.cells_with_value(5), [center, other]);
+        assert!(sg.cells_with_value(0).is_empty());
+
+        assert!(!sg.is_candidate(layout.index((0, 0), 4, 0), 5));
+        assert!(sg.is_candidate(layout.index((0, 0), 4, 0), 6));
+        let candidates = sg.candidate_cells(5);
+        assert!(!candidates.contains(&center));
+        assert!(!candidates.contains(&layout.index((0, 0), 0, 4)));
+        assert!(!candidates.contains(&layout.index((1, 1), 8, 4)));
+        assert!(candidates.contains(&layout.index((0, 0), 0, 0)));
+        assert!(candidates
+            .iter()
+            .all(|i| sg.peers(*i).iter().all(|p| sg.cells[*p] != 5)));
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut sg = SudokuGrid::new(2, 2);
+        let layout = sg.layout();
+        let corner = layout.index((0, 0), 8, 8);
+        let same_row = layout.index((0, 0), 8, 0);
+        let same_block = layout.index((0, 0), 6, 6);
+        let other_sudoku = layout.index((0, 1), 5, 2);
+        let unrelated = layout.index((1, 0), 4, 4);
+        for i in [corner, same_row, same_block, other_sudoku, unrelated] {
+            sg.cells[i] = 3;
+        }
+        sg.cells[layout.index((0, 0), 8, 4)] = 4;
+
+        let conflicts = sg.conflicts(corner);
+        let conflict = |index, region, sudoku| Conflict {
+            index,
+            region,
+            sudoku,
+        };
+        assert_eq!(
+            conflicts,
+            [
+                conflict(same_row, RegionKind::Row, (0, 0)),
+                conflict(same_block, RegionKind::Block, (0, 0)),
+                conflict(other_sudoku, RegionKind::Column, (0, 1)),
+            ]
+        );
+        assert_eq!(sg.conflicts(unrelated), []);
+        assert_eq!(sg.conflicts(layout.index((0, 0), 0, 0)), []);
+    }
+
+    #[test]
+    fn mark_wrong() {
+        let mut sg = SudokuGrid::new(1, 1);
+        let solution: Box<[u8]> = (0..sg.cells.len()).map(|i| (i % 9) as u8 + 1).collect();
+        sg.cells[0] = 1; // given
+        sg.cells[1] = 2 + 16; // correct user entry
+        sg.cells[2] = 4 + 16; // wrong, but not clashing with anything yet
+        sg.cells[3] = 16 + 64; // emptied after being wrong
+        sg.mark_wrong(&solution).unwrap();
+        assert_eq!(&sg.cells[..5], [1, 2 + 16, 4 + 16 + 64, 16, 0]);
+
+        // the conflict flag is independent
+        sg.mark_errors();
+        assert_eq!(sg.cells[2], 4 + 16 + 64);
+        sg.cells[2] = 3 + 16 + 64;
+        sg.mark_wrong(&solution).unwrap();
+        assert_eq!(sg.cells[2], 3 + 16);
+
+        // a solution for another world leaves the cells alone
+        let cells = sg.cells.clone();
+        assert_eq!(
+            sg.mark_wrong(&solution[1..]),
+            Err(SolutionLength {
+                expected: sg.cells.len(),
+                actual: sg.cells.len() - 1
+            })
+        );
+        assert_eq!(sg.cells, cells);
+    }
+
+    #[test]
+    fn regions_with_box_sizes() {
+        for (width, height) in [(2, 2), (3, 2), (2, 3), (4, 4)] {
+            let boxes = BoxSize::new(width, height).unwrap();
+            let values = boxes.values();
+            let topology = GridTopology::new(1, 1, Wrap::Bounded)
+                .with_boxes(boxes)
+                .unwrap();
+            let sg = SudokuGrid::with_topology(topology);
+            assert_eq!(sg.cells.len(), values * values);
+            let s = sg.sudoku((0, 0));
+
+            // every cell is in one row, column, and block, at the position it is stored at
+            let mut seen = vec![0; sg.cells.len()];
+            for k in 0..values {
+                for (column, i) in sg.row(s, k).indexes().enumerate() {
+                    assert_eq!(sg.layout().index((0, 0), k, column), i);
+                    seen[i] += 1;
+                }
+                for (row, i) in sg.column(s, k).indexes().enumerate() {
+                    assert_eq!(sg.layout().index((0, 0), row, k), i);
+                    seen[i] += 1;
+                }
+                for i in sg.block(s, k).indexes() {
+                    assert_eq!(sg.block_index_for(s, i), k);
+                    seen[i] += 1;
+                }
+            }
+            assert!(seen.iter().all(|count| *count == 3));
+
+            // the cells of a row and a column are in the block as well
+            let peers = 3 * (values - 1) - (width - 1) - (height - 1);
+            assert!((0..sg.cells.len()).all(|i| sg.peers(i).len() == peers));
+        }
+
+        // corner blocks are shared, so a torus sudoku stores all but two of its blocks
+        let boxes = BoxSize::new(2, 2).unwrap();
+        let sg = SudokuGrid::with_topology(
+            GridTopology::new(2, 2, Wrap::Torus)
+                .with_boxes(boxes)
+                .unwrap(),
+        );
+        assert_eq!(sg.cells.len(), 2 * 2 * 2 * 4);
+        assert_eq!(
+            sg.sudoku((0, 0)).block_start[boxes.top_right_block()],
+            sg.sudoku((1, 0)).block_start[boxes.bottom_left_block()]
+        );
+    }
+
+    #[test]
+    fn wider_cells_for_more_values() {
+        let boxes = BoxSize::new(4, 4).unwrap();
+        assert_eq!((boxes.value_mask(), boxes.user_flag()), (31, 32));
+        assert_eq!((boxes.error_flag(), boxes.wrong_flag()), (64, 128));
+        assert_eq!(BoxSize::CLASSIC.value_mask(), 15);
+
+        let topology = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(boxes)
+            .unwrap();
+        let mut sg = SudokuGrid::with_topology(topology);
+        let s = sg.sudoku((0, 0)).clone();
+        let row = sg.row(&s, 0).indexes().collect::<Vec<_>>();
+        sg.cells[row[0]] = 16 + 32; // user entered 16
+        sg.cells[row[1]] = 16;
+        sg.mark_errors();
+        assert_eq!(sg.cells[row[0]], 16 + 32 + 64);
+        assert_eq!(sg.value(row[1]), 16);
+        // (in the same row and block)
+        assert_eq!(sg.conflicts(row[0]).len(), 2);
+    }
 }
@@ -0,0 +1,466 @@
+//! SVG export of a single sudoku or the whole world
+//!
+//! The world is drawn in the same diamond layout as the WebGL view: each sudoku shares its
+//! corner blocks with the sudokus diagonally next to it, and the gaps between sudokus are
+//! gray. Givens, user entries, notes, errors, and wrong entries are styled differently, and
+//! the windows of a Windoku are shaded.
+
+use crate::sudokus::{Coords, RegionKind, SudokuGrid};
+
+/// Size of a cell in pixels (the SVG itself uses cells as units)
+const CELL_PIXELS: usize = 32;
+
+const STYLE: &str = "\
+text{font-family:'LT Superior',sans-serif;text-anchor:middle;dominant-baseline:central}\
+.cell{fill:#fff;stroke:#000;stroke-width:0.02}\
+.error{fill:#f66}\
+.wrong{fill:#fc6}\
+.window{fill:#cfe0f5}\
+.block{fill:none;stroke:#000;stroke-width:0.06}\
+.given{font-size:0.7px;font-weight:bold}\
+.user{font-size:0.7px;fill:#00388f}\
+.note{font-size:0.25px;fill:#666}\
+.shaded{fill:#e0e0e0}\
+.title{font-size:0.8px;font-weight:bold}\
+.label{font-size:0.4px}";
+
+/// Size of a booklet page (A4) in cells
+const PAGE_WIDTH: f64 = 15.0;
+const PAGE_HEIGHT: f64 = PAGE_WIDTH * 297.0 / 210.0;
+
+/// Number of sudokus per answer key page
+const ANSWERS_PER_PAGE: usize = 4;
+
+/// Collects cells at world positions, and writes them as an SVG
+struct SvgWriter<'a> {
+    sg: &'a SudokuGrid,
+    notes: Option<&'a [u16]>,
+    /// World position and cell index of every cell to draw
+    cells: Vec<((isize, isize), usize)>,
+    /// World position of the top left cell of every block to draw
+    blocks: Vec<(isize, isize)>,
+    /// World position of the top left cell of blocks with a gray background
+    shaded: Vec<(isize, isize)>,
+    /// Cells that are part of a window of a sudoku to draw (see
+    /// [`Rules::windows`](crate::rules::Rules::windows))
+    windows: Vec<usize>,
+}
+
+impl<'a> SvgWriter<'a> {
+    fn new(sg: &'a SudokuGrid, notes: Option<&'a [u16]>) -> Self {
+        SvgWriter {
+            sg,
+            notes,
+            cells: Vec::new(),
+            blocks: Vec::new(),
+            shaded: Vec::new(),
+            windows: Vec::new(),
+        }
+    }
+
+    /// Add a sudoku with its top left cell at the given world position
+    fn add_sudoku(&mut self, coords: Coords, (ox, oy): (isize, isize)) {
+        let s = self.sg.sudoku(coords);
+        let boxes = self.sg.boxes();
+        for row in 0..boxes.values() {
+            for (column, i) in self.sg.row(s, row).indexes().enumerate() {
+                self.cells
+                    .push(((ox + column as isize, oy + row as isize), i));
+            }
+        }
+        for block in 0..boxes.values() {
+            let (row, column) = boxes.position(block, 0);
+            self.blocks.push((ox + column as isize, oy + row as isize));
+        }
+        for (kind, indexes) in self.sg.extra_regions(s) {
+            if *kind == RegionKind::Window {
+                self.windows.extend(indexes.iter());
+            }
+        }
+    }
+
+    fn write_text(svg: &mut String, class: &str, x: f64, y: f64, text: impl std::fmt::Display) {
+        svg.push_str(&format!(
+            "<text class=\"{class}\" x=\"{x}\" y=\"{y}\">{text}</text>\n"
+        ));
+    }
+
+    /// Get the bounding box of all cells as (x, y, width, height)
+    fn bounds(&self) -> (isize, isize, isize, isize) {
+        let min_x = self.cells.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+        let min_y = self.cells.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+        let max_x = self
+            .cells
+            .iter()
+            .map(|((x, _), _)| *x + 1)
+            .max()
+            .unwrap_or(0);
+        let max_y = self
+            .cells
+            .iter()
+            .map(|((_, y), _)| *y + 1)
+            .max()
+            .unwrap_or(0);
+        (min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Write the cells, blocks, and numbers (without the surrounding `<svg>` element)
+    fn write_cells(&mut self, svg: &mut String) {
+        // shared blocks are added by both sudokus
+        self.cells.sort_unstable();
+        self.cells.dedup();
+        self.blocks.sort_unstable();
+        self.blocks.dedup();
+
+        let boxes = self.sg.boxes();
+        let (width, height) = (boxes.width as isize, boxes.height as isize);
+        for ((x, y), i) in self.cells.iter() {
+            let cell = self.sg.cells[*i];
+            let block = (x - x.rem_euclid(width), y - y.rem_euclid(height));
+            let class = if cell & boxes.error_flag() > 0 {
+                "cell error"
+            } else if cell & boxes.wrong_flag() > 0 {
+                "cell wrong"
+            } else if self.windows.contains(i) {
+                "cell window"
+            } else if self.shaded.contains(&block) {
+                "cell shaded"
+            } else {
+                "cell"
+            };
+            svg.push_str(&format!(
+                "<rect class=\"{class}\" x=\"{x}\" y=\"{y}\" width=\"1\" height=\"1\"/>\n"
+            ));
+        }
+        for (x, y) in self.blocks.iter() {
+            svg.push_str(&format!(
+                "<rect class=\"block\" x=\"{x}\" y=\"{y}\" width=\"{width}\" \
+                 height=\"{height}\"/>\n"
+            ));
+        }
+
+        // notes are laid out like the cells of a block
+        let (width, height) = (boxes.width as f64, boxes.height as f64);
+        for ((x, y), i) in self.cells.iter() {
+            let (x, y) = (*x as f64, *y as f64);
+            let cell = self.sg.cells[*i];
+            let value = cell & boxes.value_mask();
+            if value > 0 {
+                let class = if cell & boxes.user_flag() > 0 {
+                    "user"
+                } else {
+                    "given"
+                };
+                Self::write_text(svg, class, x + 0.5, y + 0.5, value);
+            } else if let Some(notes) = self.notes.and_then(|notes| notes.get(*i)) {
+                let notes = u32::from(*notes);
+                for note in (1..=boxes.values()).filter(|v| notes & (1 << v) > 0) {
+                    let nx = x + (((note - 1) % boxes.width) as f64 + 0.5) / width;
+                    let ny = y + (((note - 1) / boxes.width) as f64 + 0.5) / height;
+                    Self::write_text(svg, "note", nx, ny, note);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let (x, y, width, height) = self.bounds();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             viewBox=\"{x} {y} {width} {height}\">\n<style>{STYLE}</style>\n",
+            width as usize * CELL_PIXELS,
+            height as usize * CELL_PIXELS,
+        );
+        if self.cells.len() > self.sg.boxes().values().pow(2) {
+            // gaps between sudokus
+            svg.push_str(&format!(
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{width}\" height=\"{height}\" \
+                 fill=\"#d4d4d4\"/>\n"
+            ));
+        }
+        self.write_cells(&mut svg);
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Start an A4 page (in units of cells, with the page being 15 cells wide)
+fn page_start(title: &str) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"210mm\" height=\"297mm\" \
+         viewBox=\"0 0 {PAGE_WIDTH} {PAGE_HEIGHT}\">\n<style>{STYLE}</style>\n"
+    );
+    SvgWriter::write_text(&mut svg, "title", PAGE_WIDTH / 2.0, 1.8, title);
+    svg
+}
+
+fn page_end(mut svg: String, page: usize) -> String {
+    SvgWriter::write_text(&mut svg, "label", PAGE_WIDTH / 2.0, PAGE_HEIGHT - 0.8, page);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+impl SudokuGrid {
+    /// Draw a single sudoku as SVG
+    ///
+    /// `notes` optionally contains a bitmap of pencil marks for every cell (bit `v` set
+    /// for value `v`), which are drawn in empty cells.
+    pub fn sudoku_svg(&self, coords: Coords, notes: Option<&[u16]>) -> String {
+        let mut writer = SvgWriter::new(self, notes);
+        writer.add_sudoku(coords, (0, 0));
+        writer.finish()
+    }
+
+    /// Draw all sudokus of the world as SVG, in the layout of the WebGL view
+    ///
+    /// See [`SudokuGrid::sudoku_svg`] for the format of `notes`.
+    pub fn world_svg(&self, notes: Option<&[u16]>) -> String {
+        let mut writer = SvgWriter::new(self, notes);
+        let layout = self.layout();
+        for coords in self.sudoku_coords() {
+            writer.add_sudoku(coords, layout.origin(coords));
+        }
+        writer.finish()
+    }
+
+    /// Create a printable booklet of SVG pages: one page for every sudoku, followed by an
+    /// answer key
+    ///
+    /// Blocks that are shared with another sudoku are shaded and labelled with the
+    /// coordinates of that sudoku. The answer key is solved from the givens, visiting at
+    /// most `max_nodes` nodes of the search tree, and is left out if they have no solution
+    /// or the solver runs out of nodes.
+    pub fn booklet_svg(&self, max_nodes: usize) -> Vec<String> {
+        let mut pages = Vec::new();
+        let grid_x = (PAGE_WIDTH - 9.0) / 2.0;
+        let grid_y = 4.0;
+        // sudokus are drawn 9 cells wide whatever the size of their blocks
+        let boxes = self.boxes();
+        let values = boxes.values();
+        let scale = 9.0 / values as f64;
+
+        for (x, y) in self.sudoku_coords() {
+            let mut svg = page_start(&format!("Sudoku ({x}, {y})"));
+
+            let mut writer = SvgWriter::new(self, None);
+            writer.add_sudoku((x, y), (0, 0));
+            svg.push_str(&format!(
+                "<g transform=\"translate({grid_x} {grid_y}) scale({scale})\">\n"
+            ));
+            for block in 0..values {
+                let shared = self.shared_with((x, y), block);
+                if shared.is_empty() {
+                    continue;
+                }
+                let (by, bx) = boxes.position(block, 0);
+                writer.shaded.push((bx as isize, by as isize));
+                let label = shared
+                    .iter()
+                    .map(|(ox, oy)| format!("({ox}, {oy})"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                // labels go outside of the grid, next to the block (the center block can
+                // only be shaded)
+                let (center_x, center_y) = (
+                    bx as f64 + boxes.width as f64 / 2.0,
+                    by as f64 + boxes.height as f64 / 2.0,
+                );
+                let (label_x, label_y) = match (block % boxes.across(), block / boxes.across()) {
+                    (_, 0) => (center_x, -0.4),
+                    (_, row) if row == boxes.down() - 1 => (center_x, values as f64 + 0.5),
+                    (0, _) => (-1.6, center_y + 0.1),
+                    (column, _) if column == boxes.across() - 1 => {
+                        (values as f64 + 1.6, center_y + 0.1)
+                    }
+                    _ => continue,
+                };
+                SvgWriter::write_text(&mut svg, "label", label_x, label_y, label);
+            }
+            writer.write_cells(&mut svg);
+            svg.push_str("</g>\n");
+
+            SvgWriter::write_text(
+                &mut svg,
+                "label",
+                PAGE_WIDTH / 2.0,
+                grid_y + 11.0,
+                "Shaded blocks are shared with the sudoku named next to them",
+            );
+            pages.push(page_end(svg, pages.len() + 1));
+        }
+
+        let mut givens = self.empty_like();
+        for (given, cell) in givens.cells.iter_mut().zip(self.cells.iter()) {
+            if cell & boxes.user_flag() == 0 {
+                *given = cell & boxes.value_mask();
+            }
+        }
+        let solutions = givens.solutions_within(1, max_nodes);
+        let Some(solution) = solutions.and_then(|mut solutions| solutions.pop()) else {
+            return pages;
+        };
+        givens.cells = solution;
+
+        let coords = self.sudoku_coords().collect::<Vec<_>>();
+        let answer_scale = 0.7 * scale;
+        for answers in coords.chunks(ANSWERS_PER_PAGE) {
+            let mut svg = page_start("Answers");
+            for (i, (x, y)) in answers.iter().enumerate() {
+                let ax = 1.0 + 6.8 * (i % 2) as f64;
+                let ay = 4.0 + 8.0 * (i / 2) as f64;
+                SvgWriter::write_text(
+                    &mut svg,
+                    "label",
+                    ax + 3.15,
+                    ay - 0.5,
+                    format!("({x}, {y})"),
+                );
+
+                let mut writer = SvgWriter::new(&givens, None);
+                writer.add_sudoku((*x, *y), (0, 0));
+                svg.push_str(&format!(
+                    "<g transform=\"translate({ax} {ay}) scale({answer_scale})\">\n"
+                ));
+                writer.write_cells(&mut svg);
+                svg.push_str("</g>\n");
+            }
+            pages.push(page_end(svg, pages.len() + 1));
+        }
+
+        pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::sudokus::BoxSize;
+    use crate::topology::{GridTopology, Wrap};
+
+    #[test]
+    fn sudoku_svg() {
+        let mut sg = SudokuGrid::new(2, 2);
+        sg.cells[0] = 3; // given
+        sg.cells[1] = 4 + 16; // user entered
+        sg.cells[2] = 4 + 16 + 32; // user entered error
+        sg.cells[4] = 5 + 16 + 64; // user entered wrong
+        let mut notes = vec![0; sg.cells.len()];
+        notes[3] = (1 << 2) | (1 << 9);
+
+        let svg = sg.sudoku_svg((0, 0), Some(&notes));
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains("viewBox=\"0 0 9 9\""));
+        assert_eq!(svg.matches("class=\"cell").count(), 81);
+        assert_eq!(svg.matches("class=\"cell error\"").count(), 1);
+        assert_eq!(svg.matches("class=\"cell wrong\"").count(), 1);
+        assert_eq!(svg.matches("class=\"block\"").count(), 9);
+        assert_eq!(svg.matches("class=\"given\"").count(), 1);
+        assert_eq!(svg.matches("class=\"user\"").count(), 3);
+        assert_eq!(svg.matches("class=\"note\"").count(), 2);
+    }
+
+    #[test]
+    fn sudoku_svg_windows() {
+        let mut sg = SudokuGrid::new(2, 2).with_rules(Rules {
+            windows: true,
+            ..Rules::default()
+        });
+        let layout = sg.layout();
+        sg.cells[layout.index((0, 0), 1, 1)] = 4 + 16 + 32; // errors are still red
+
+        // only the windows of the sudoku itself are shaded, not those of its neighbours
+        // reaching into its corner blocks
+        let svg = sg.sudoku_svg((0, 0), None);
+        assert_eq!(svg.matches("class=\"cell window\"").count(), 4 * 9 - 1);
+        assert_eq!(svg.matches("class=\"cell error\"").count(), 1);
+        assert!(!SudokuGrid::new(2, 2)
+            .sudoku_svg((0, 0), None)
+            .contains("window\""));
+    }
+
+    #[test]
+    fn svg_other_box_sizes() {
+        let topology = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(BoxSize::new(3, 2).unwrap())
+            .unwrap();
+        let mut sg = SudokuGrid::with_topology(topology);
+        sg.cells[0] = 6 + 16;
+        let mut notes = vec![0; sg.cells.len()];
+        notes[1] = (1 << 1) | (1 << 6);
+
+        let svg = sg.sudoku_svg((0, 0), Some(&notes));
+        assert!(svg.contains("viewBox=\"0 0 6 6\""));
+        assert_eq!(svg.matches("class=\"cell").count(), 36);
+        assert_eq!(svg.matches("width=\"3\" height=\"2\"").count(), 6);
+        assert_eq!(svg.matches("class=\"user\"").count(), 1);
+        assert_eq!(svg.matches("class=\"note\"").count(), 2);
+
+        let pages = sg.booklet_svg(100_000);
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("scale(1.5)"));
+        assert_eq!(pages[1].matches("class=\"given\"").count(), 36);
+
+        // 16 values take a fifth bit, so the flags move up one bit
+        let topology = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(BoxSize::new(4, 4).unwrap())
+            .unwrap();
+        let mut sg = SudokuGrid::with_topology(topology);
+        let boxes = sg.boxes();
+        sg.cells[0] = 16 + boxes.user_flag();
+        sg.cells[1] = 3 + boxes.error_flag();
+        let svg = sg.sudoku_svg((0, 0), None);
+        assert_eq!(svg.matches("class=\"cell").count(), 256);
+        assert_eq!(svg.matches("class=\"cell error\"").count(), 1);
+        assert!(svg.contains(">16</text>"));
+        assert_eq!(svg.matches("class=\"given\"").count(), 1);
+    }
+
+    #[test]
+    fn world_svg_draws_shared_blocks_once() {
+        // (0, 0) - (1, 0) - (1, 1) - (0, 1) form a ring of 4 overlapping sudokus, the
+        // blocks shared across the edges of the torus are drawn on both sides
+        let sg = SudokuGrid::new(2, 2);
+        let svg = sg.world_svg(None);
+        assert_eq!(svg.matches("class=\"cell").count(), 81 * 4 - 9 * 4);
+        assert_eq!(svg.matches("class=\"block\"").count(), 9 * 4 - 4);
+    }
+
+    #[test]
+    fn booklet_svg() {
+        let mut sg = SudokuGrid::new(3, 2);
+        sg.cells = sg.solutions(1).remove(0);
+        let solution = sg.cells.clone();
+        for i in (0..sg.cells.len()).step_by(2) {
+            sg.cells[i] = 0;
+        }
+        sg.cells[1] = solution[1] + 16; // user entries are left out of the answers
+
+        let pages = sg.booklet_svg(100_000);
+        assert_eq!(pages.len(), 6 + 2);
+
+        let first = &pages[0];
+        assert!(first.contains("Sudoku (0, 0)"));
+        assert_eq!(first.matches("class=\"cell shaded\"").count(), 4 * 9);
+        for neighbour in ["(0, 1)", "(1, 0)", "(2, 0)"] {
+            assert!(first.contains(neighbour), "missing label {neighbour}");
+        }
+
+        let answers = &pages[6..];
+        assert!(answers[0].contains("Answers"));
+        let answer_count = |page: &String| page.matches("class=\"given\"").count();
+        assert_eq!(answer_count(&answers[0]), 81 * 4);
+        assert_eq!(answer_count(&answers[1]), 81 * 2);
+
+        // without enough nodes to solve the givens there is no answer key
+        assert_eq!(sg.booklet_svg(10).len(), 6);
+    }
+
+    #[test]
+    fn booklet_svg_without_solution() {
+        let mut sg = SudokuGrid::new(1, 1);
+        sg.cells[0] = 1;
+        sg.cells[1] = 1;
+        assert_eq!(sg.booklet_svg(100_000).len(), 1);
+    }
+}
@@ -0,0 +1,533 @@
+//! Import and export of worlds as standard 81-character sudoku strings
+//!
+//! Every line describes one sudoku as `x,y <digits>`, where `<digits>` lists the 81 cells
+//! in row-major order with `.` (or `0`) for empty cells. This lets ordinary sudoku tools
+//! work on one 9x9 sudoku of the world at a time. Presets start with a `preset <name>`
+//! line (see [`Preset::name`]), and edge tilings with an `edges <overlap>` line (see
+//! [`EdgeOverlap::name`]).
+//!
+//! Worlds with blocks of another size start with a `boxes WxH` line instead, and have a
+//! cell for every position of their larger or smaller sudokus. Values from 10 on are
+//! written as letters (`A` for 10, `G` for 16). Worlds that are not a torus have a
+//! `wrap <name>` line (see [`Wrap::name`]), after the `boxes` line if there is one.
+
+use std::collections::HashSet;
+
+use crate::sudokus::{BoxSize, Coords, SudokuGrid};
+use crate::topology::{EdgeOverlap, EdgeTopology, GridTopology, Preset, Wrap};
+
+#[derive(Debug, PartialEq)]
+pub enum TextError {
+    /// Line is not of the form `x,y <digits>`
+    InvalidLine(usize),
+    /// Sudoku string on this line does not contain exactly 81 cells (or as many as the
+    /// sudokus of the world have)
+    WrongLength(usize),
+    /// Character that is not `.` or a value of the sudoku
+    InvalidCharacter(usize, char),
+    /// Sudoku is described more than once
+    DuplicateSudoku(Coords),
+    /// Sudoku is not described at all
+    MissingSudoku(Coords),
+    /// Line is not `preset <name>`, `edges <overlap>` or `wrap <name>` with a known name,
+    /// or `boxes WxH` with a supported block size, or it does not go together with the
+    /// lines before it
+    UnknownTopology(usize),
+    /// Sudoku is not part of the preset
+    NotInPreset(Coords),
+    /// Two neighbouring sudokus disagree about a cell in a shared block
+    SharedBlockMismatch {
+        index: usize,
+        sudoku: Coords,
+        other: Coords,
+    },
+}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TextError::InvalidLine(line) => write!(f, "line {line}: expected `x,y <digits>`"),
+            TextError::WrongLength(line) => {
+                write!(f, "line {line}: wrong number of cells for the sudoku")
+            }
+            TextError::InvalidCharacter(line, c) => {
+                write!(f, "line {line}: invalid character {c:?}")
+            }
+            TextError::DuplicateSudoku((x, y)) => write!(f, "sudoku ({x}, {y}) is given twice"),
+            TextError::MissingSudoku((x, y)) => write!(f, "sudoku ({x}, {y}) is missing"),
+            TextError::UnknownTopology(line) => write!(f, "line {line}: unknown topology"),
+            TextError::NotInPreset((x, y)) => {
+                write!(f, "sudoku ({x}, {y}) is not part of the preset")
+            }
+            TextError::SharedBlockMismatch {
+                index,
+                sudoku,
+                other,
+            } => write!(
+                f,
+                "sudokus {sudoku:?} and {other:?} disagree about shared cell {index}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// Character for a value (`.` for empty cells)
+fn value_char(value: u8) -> char {
+    match value {
+        0 => '.',
+        1..=9 => (b'0' + value) as char,
+        _ => (b'A' + value - 10) as char,
+    }
+}
+
+/// Parse a `WxH` block size
+fn parse_boxes(size: &str) -> Option<BoxSize> {
+    let (width, height) = size.split_once('x')?;
+    let boxes = BoxSize {
+        width: width.trim().parse().ok()?,
+        height: height.trim().parse().ok()?,
+    };
+    boxes.is_valid().then_some(boxes)
+}
+
+/// Parse one line of the text format (line numbers start at 1 for error messages)
+fn parse_line(
+    line_number: usize,
+    line: &str,
+    boxes: BoxSize,
+) -> Result<(Coords, Vec<u8>), TextError> {
+    let invalid_line = || TextError::InvalidLine(line_number);
+    let (coords, digits) = line.trim().split_once(' ').ok_or_else(invalid_line)?;
+    let (x, y) = coords.split_once(',').ok_or_else(invalid_line)?;
+    let x = x.trim().parse().map_err(|_| invalid_line())?;
+    let y = y.trim().parse().map_err(|_| invalid_line())?;
+
+    let digits = digits.trim();
+    let count = boxes.values();
+    if digits.chars().count() != count * count {
+        return Err(TextError::WrongLength(line_number));
+    }
+    let mut values = vec![0; count * count];
+    for (value, c) in values.iter_mut().zip(digits.chars()) {
+        *value = match c {
+            '.' => 0,
+            '0'..='9' => c as u8 - b'0',
+            'A'..='Z' => c as u8 - b'A' + 10,
+            _ => return Err(TextError::InvalidCharacter(line_number, c)),
+        };
+        if *value as usize > count {
+            return Err(TextError::InvalidCharacter(line_number, c));
+        }
+    }
+
+    Ok(((x, y), values))
+}
+
+impl SudokuGrid {
+    /// Export every sudoku as `x,y <81 digits>` (one per line, `.` for empty cells)
+    pub fn to_text(&self) -> String {
+        let boxes = self.boxes();
+        let mut text = String::new();
+        if boxes != BoxSize::CLASSIC {
+            text.push_str(&format!("boxes {}x{}\n", boxes.width, boxes.height));
+        }
+        if let Some(preset) = self.preset() {
+            text.push_str(&format!("preset {}\n", preset.name()));
+        }
+        if let Some(overlap) = self.edge_overlap() {
+            text.push_str(&format!("edges {}\n", overlap.name()));
+        }
+        if let Some(wrap) = self.wrap().filter(|w| *w != Wrap::Torus) {
+            text.push_str(&format!("wrap {}\n", wrap.name()));
+        }
+        for (x, y) in self.sudoku_coords() {
+            let s = self.sudoku((x, y));
+            text.push_str(&format!("{x},{y} "));
+            for row in 0..boxes.values() {
+                for i in self.row(s, row).indexes() {
+                    text.push(value_char(self.value(i)));
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Import a world exported with [`SudokuGrid::to_text`]
+    ///
+    /// The world size is derived from the largest coordinates, every sudoku must be
+    /// present exactly once, and shared blocks must agree between the sudokus sharing
+    /// them. Empty lines and lines starting with `#` are ignored.
+    pub fn from_text(text: &str) -> Result<SudokuGrid, TextError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .peekable();
+        let (mut preset, mut overlap, mut boxes, mut wrap) = (None, None, None, None);
+        let topology_line =
+            |(_, line): &(usize, &str)| line.trim_start().starts_with(char::is_alphabetic);
+        while let Some((i, line)) = lines.next_if(topology_line) {
+            let unknown = || TextError::UnknownTopology(i + 1);
+            // presets and edge tilings have their own blocks and wrap, so they come alone,
+            // and the other lines come once each
+            if preset.is_some() || overlap.is_some() {
+                return Err(unknown());
+            }
+            match line.trim().split_once(' ').ok_or_else(unknown)? {
+                ("preset", name) if boxes.is_none() && wrap.is_none() => {
+                    preset = Some(Preset::from_name(name.trim()).ok_or_else(unknown)?)
+                }
+                ("edges", name) if boxes.is_none() && wrap.is_none() => {
+                    overlap = Some(EdgeOverlap::from_name(name.trim()).ok_or_else(unknown)?)
+                }
+                ("boxes", size) if boxes.is_none() && wrap.is_none() => {
+                    boxes = Some(parse_boxes(size.trim()).ok_or_else(unknown)?)
+                }
+                ("wrap", name) if wrap.is_none() => {
+                    let name = Wrap::from_name(name.trim()).ok_or_else(unknown)?;
+                    // blocks shared across a twisted join have to be square
+                    GridTopology::new(1, 1, name)
+                        .with_boxes(boxes.unwrap_or_default())
+                        .map_err(|_| unknown())?;
+                    wrap = Some(name);
+                }
+                _ => return Err(unknown()),
+            }
+        }
+        let boxes = boxes.unwrap_or_default();
+        let sudokus = lines
+            .map(|(i, line)| parse_line(i + 1, line, boxes))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut sg = match preset {
+            Some(preset) => SudokuGrid::with_topology(preset),
+            None => {
+                let n = sudokus.iter().map(|((x, _), _)| x + 1).max().unwrap_or(0);
+                let m = sudokus.iter().map(|((_, y), _)| y + 1).max().unwrap_or(0);
+                if n == 0 || m == 0 {
+                    return Err(TextError::MissingSudoku((0, 0)));
+                }
+                if n.checked_mul(m).is_none_or(|count| count > sudokus.len()) {
+                    // not every sudoku has a line, so one of the first ones (in the order of
+                    // `sudoku_coords`) is missing; this is checked before allocating a grid
+                    // that could be far too large for the text
+                    let given = sudokus.iter().map(|(c, _)| *c).collect::<HashSet<_>>();
+                    let missing = (0..=given.len())
+                        .map(|k| (k % n, k / n))
+                        .find(|c| !given.contains(c))
+                        .expect("fewer sudokus are given than there are");
+                    return Err(TextError::MissingSudoku(missing));
+                }
+                match overlap {
+                    Some(overlap) => SudokuGrid::with_topology(EdgeTopology::new(n, m, overlap)),
+                    None => SudokuGrid::with_topology(
+                        GridTopology::new(n, m, wrap.unwrap_or_default())
+                            .with_boxes(boxes)
+                            .expect("the blocks are checked with the wrap line"),
+                    ),
+                }
+            }
+        };
+        let n = sg.n;
+        let mut set_by = vec![None; sg.cells.len()];
+        let mut seen = vec![false; sg.n * sg.m];
+        for (coords, values) in sudokus {
+            if !sg.has_sudoku(coords) {
+                return Err(TextError::NotInPreset(coords));
+            }
+            if std::mem::replace(&mut seen[coords.0 + coords.1 * n], true) {
+                return Err(TextError::DuplicateSudoku(coords));
+            }
+
+            let s = sg.sudoku(coords);
+            let indexes = (0..boxes.values())
+                .flat_map(|row| sg.row(s, row).indexes())
+                .collect::<Vec<_>>();
+            for (i, value) in indexes.into_iter().zip(values) {
+                match set_by[i] {
+                    Some(other) if sg.cells[i] != value => {
+                        return Err(TextError::SharedBlockMismatch {
+                            index: i,
+                            sudoku: coords,
+                            other,
+                        });
+                    }
+                    _ => {
+                        sg.cells[i] = value;
+                        set_by[i] = Some(coords);
+                    }
+                }
+            }
+        }
+
+        if let Some(coords) = sg.sudoku_coords().find(|(x, y)| !seen[x + y * n]) {
+            return Err(TextError::MissingSudoku(coords));
+        }
+
+        Ok(sg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_grid(n: usize, m: usize) -> SudokuGrid {
+        let mut sg = SudokuGrid::new(n, m);
+        for (i, cell) in sg.cells.iter_mut().enumerate() {
+            *cell = (i % 10) as u8;
+        }
+        sg
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let sg = numbered_grid(3, 2);
+        let text = sg.to_text();
+        assert_eq!(text.lines().count(), 6);
+        assert!(text
+            .lines()
+            .all(|l| l.split_once(' ').unwrap().1.len() == 81));
+
+        let imported = SudokuGrid::from_text(&text).unwrap();
+        assert_eq!((imported.n, imported.m), (3, 2));
+        assert_eq!(imported.cells, sg.cells);
+    }
+
+    #[test]
+    fn text_wraps() {
+        for wrap in Wrap::ALL {
+            let mut sg = SudokuGrid::with_wrap(3, 2, wrap);
+            for (i, cell) in sg.cells.iter_mut().enumerate() {
+                *cell = (i % 10) as u8;
+            }
+            let text = sg.to_text();
+            let header = format!("wrap {}", wrap.name());
+            assert_eq!(
+                text.lines().next() == Some(header.as_str()),
+                wrap != Wrap::Torus
+            );
+
+            let imported = SudokuGrid::from_text(&text).unwrap();
+            assert_eq!((imported.n, imported.m), (3, 2));
+            assert_eq!(imported.wrap(), Some(wrap));
+            assert_eq!(imported.cells, sg.cells);
+        }
+
+        let boxes = BoxSize::new(2, 2).unwrap();
+        let topology = GridTopology::new(2, 1, Wrap::Bounded)
+            .with_boxes(boxes)
+            .unwrap();
+        let text = SudokuGrid::with_topology(topology).to_text();
+        assert!(text.starts_with("boxes 2x2\nwrap bounded\n"));
+        let imported = SudokuGrid::from_text(&text).unwrap();
+        assert_eq!(
+            (imported.boxes(), imported.wrap()),
+            (boxes, Some(Wrap::Bounded))
+        );
+
+        let (_, sudokus) = text.split_once("bounded\n").unwrap();
+        assert_eq!(
+            SudokuGrid::from_text(&format!("wrap sphere\n{sudokus}")).err(),
+            Some(TextError::UnknownTopology(1))
+        );
+        // blocks that are not square can not be mirrored across a Möbius strip
+        let wide = GridTopology::new(1, 1, Wrap::Bounded)
+            .with_boxes(BoxSize::new(3, 2).unwrap())
+            .unwrap();
+        let text = SudokuGrid::with_topology(wide).to_text();
+        assert_eq!(
+            SudokuGrid::from_text(&text.replace("bounded", "mobius")).err(),
+            Some(TextError::UnknownTopology(2))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&text.replace("bounded", "klein")).err(),
+            Some(TextError::UnknownTopology(2))
+        );
+
+        // the wrap of a preset is part of the preset
+        assert_eq!(
+            SudokuGrid::from_text(&format!("preset samurai\nwrap torus\n{sudokus}")).err(),
+            Some(TextError::UnknownTopology(2))
+        );
+    }
+
+    #[test]
+    fn text_presets() {
+        for preset in Preset::ALL {
+            let mut sg = SudokuGrid::with_topology(preset);
+            for (i, cell) in sg.cells.iter_mut().enumerate() {
+                *cell = (i % 10) as u8;
+            }
+            let text = sg.to_text();
+            assert_eq!(
+                text.lines().next(),
+                Some(format!("preset {}", preset.name()).as_str())
+            );
+            assert_eq!(text.lines().count(), sg.sudoku_coords().count() + 1);
+
+            let imported = SudokuGrid::from_text(&text).unwrap();
+            assert_eq!(imported.preset(), Some(preset));
+            assert_eq!(imported.cells, sg.cells);
+        }
+
+        let samurai = SudokuGrid::with_topology(Preset::Samurai).to_text();
+        let (_, sudokus) = samurai.split_once('\n').unwrap();
+        assert_eq!(
+            SudokuGrid::from_text(&format!("preset sumo\n{sudokus}")).err(),
+            Some(TextError::UnknownTopology(1))
+        );
+        let corner = format!("0,0 {}", ".".repeat(81));
+        assert_eq!(
+            SudokuGrid::from_text(&format!("{samurai}{corner}")).err(),
+            Some(TextError::NotInPreset((0, 0)))
+        );
+        let without_center = samurai.replace("1,1 ", "# 1,1 ");
+        assert_eq!(
+            SudokuGrid::from_text(&without_center).err(),
+            Some(TextError::MissingSudoku((1, 1)))
+        );
+    }
+
+    #[test]
+    fn text_edge_tilings() {
+        for overlap in EdgeOverlap::ALL {
+            let mut sg = SudokuGrid::with_topology(EdgeTopology::new(3, 2, overlap));
+            for (i, cell) in sg.cells.iter_mut().enumerate() {
+                *cell = (i % 10) as u8;
+            }
+            let text = sg.to_text();
+            assert!(text.starts_with(&format!("edges {}\n", overlap.name())));
+
+            let imported = SudokuGrid::from_text(&text).unwrap();
+            assert_eq!((imported.n, imported.m), (3, 2));
+            assert_eq!(imported.edge_overlap(), Some(overlap));
+            assert_eq!(imported.cells, sg.cells);
+        }
+        assert_eq!(
+            SudokuGrid::from_text("edges\n").err(),
+            Some(TextError::UnknownTopology(1))
+        );
+    }
+
+    #[test]
+    fn text_box_sizes() {
+        for (width, height) in [(2, 2), (3, 2), (4, 4)] {
+            let boxes = BoxSize::new(width, height).unwrap();
+            let topology = GridTopology::new(2, 1, Wrap::Torus)
+                .with_boxes(boxes)
+                .unwrap();
+            let mut sg = SudokuGrid::with_topology(topology);
+            for (i, cell) in sg.cells.iter_mut().enumerate() {
+                *cell = (i % (boxes.values() + 1)) as u8;
+            }
+            let text = sg.to_text();
+            assert_eq!(
+                text.lines().next(),
+                Some(format!("boxes {width}x{height}").as_str())
+            );
+            let cells = boxes.values() * boxes.values();
+            assert!(text.lines().skip(1).all(|l| l.len() == 4 + cells));
+
+            let imported = SudokuGrid::from_text(&text).unwrap();
+            assert_eq!(imported.boxes(), boxes);
+            assert_eq!(imported.cells, sg.cells);
+        }
+
+        let text = SudokuGrid::with_topology(
+            GridTopology::new(1, 1, Wrap::Torus)
+                .with_boxes(BoxSize::new(4, 4).unwrap())
+                .unwrap(),
+        )
+        .to_text();
+        // change row 5, column 5, which is in a block that is not shared
+        let with_value = |c: &str| {
+            let at = text.find("0,0 ").unwrap() + 4 + 5 * 16 + 5;
+            format!("{}{c}{}", &text[..at], &text[at + 1..])
+        };
+        let imported = SudokuGrid::from_text(&with_value("G")).unwrap();
+        assert!(imported.cells.contains(&16));
+        assert_eq!(
+            SudokuGrid::from_text(&with_value("H")).err(),
+            Some(TextError::InvalidCharacter(2, 'H'))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&text.replace("4x4", "1x4")).err(),
+            Some(TextError::UnknownTopology(1))
+        );
+    }
+
+    #[test]
+    fn text_row_major() {
+        let mut sg = SudokuGrid::new(1, 1);
+        let s = sg.sudoku((0, 0)).clone();
+        sg.set_block(&s, crate::sudokus::TOP_CENTER_BLOCK, 1..=9);
+        let text = sg.to_text();
+        assert_eq!(&text[..13], "0,0 ...123...");
+        assert_eq!(&text[13..22], "...456...");
+    }
+
+    #[test]
+    fn text_ignores_comments_and_flags() {
+        let mut sg = numbered_grid(1, 1);
+        sg.cells[3] += 16 + 32; // flags are not part of the text format
+        let text = format!("# comment\n\n{}", sg.to_text());
+        let imported = SudokuGrid::from_text(&text).unwrap();
+        assert_eq!(imported.cells[3], 3);
+    }
+
+    #[test]
+    fn text_errors() {
+        let sg = numbered_grid(2, 1);
+        let text = sg.to_text();
+        let (first, second) = text.split_once('\n').unwrap();
+
+        assert_eq!(
+            SudokuGrid::from_text(second).err(),
+            Some(TextError::MissingSudoku((0, 0)))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&format!("{text}{first}")).err(),
+            Some(TextError::DuplicateSudoku((0, 0)))
+        );
+        assert_eq!(
+            SudokuGrid::from_text("0,0 123").err(),
+            Some(TextError::WrongLength(1))
+        );
+
+        // a far away sudoku does not make a huge world
+        let far = format!("100000,100000 {}", ".".repeat(81));
+        assert_eq!(
+            SudokuGrid::from_text(&far).err(),
+            Some(TextError::MissingSudoku((0, 0)))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&format!("{text}2,5 {}", ".".repeat(81))).err(),
+            Some(TextError::MissingSudoku((2, 0)))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&first.replace(' ', ";")).err(),
+            Some(TextError::InvalidLine(1))
+        );
+        assert_eq!(
+            SudokuGrid::from_text(&first.replacen('1', "x", 1)).err(),
+            Some(TextError::InvalidCharacter(1, 'x'))
+        );
+
+        // change a cell in the top right block of (0, 0), which is shared with (1, 0)
+        let mut chars = first.chars().collect::<Vec<_>>();
+        chars[4 + 7] = if chars[4 + 7] == '5' { '6' } else { '5' };
+        let changed = chars.into_iter().collect::<String>();
+        assert!(matches!(
+            SudokuGrid::from_text(&format!("{changed}\n{second}")).err(),
+            Some(TextError::SharedBlockMismatch {
+                sudoku: (1, 0),
+                other: (0, 0),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,705 @@
+//! Which sudokus a world is made of, and which blocks they share
+//!
+//! The sudokus of a world are placed on a grid of n by m sudoku coordinates (not every
+//! position has to hold a sudoku), and any block of a sudoku can be shared with a block of
+//! another one. [`Layout`](crate::layout::Layout) decides where every cell is stored from
+//! this alone, so the grid, solver and generator work on any [`Topology`].
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::layout::{grid_origin, sudoku_origin};
+use crate::sudokus::{
+    BoxSize, Coords, BOTTOM_CENTER_BLOCK, MIDDLE_LEFT_BLOCK, MIDDLE_RIGHT_BLOCK, TOP_CENTER_BLOCK,
+    TOP_LEFT_BLOCK,
+};
+
+/// Block of another sudoku that a block is shared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedBlock {
+    pub sudoku: Coords,
+    pub block: usize,
+    /// Whether the two sudokus see the block mirrored compared to each other (see
+    /// [`BoxSize::mirror`])
+    pub mirrored: bool,
+}
+
+pub trait Topology: std::fmt::Debug {
+    /// Size (n, m) of the grid of sudoku coordinates
+    fn size(&self) -> (usize, usize);
+
+    /// Size of the blocks of every sudoku (3x3 by default)
+    fn box_size(&self) -> BoxSize {
+        BoxSize::CLASSIC
+    }
+
+    /// Whether there is a sudoku at these coordinates (every position of the grid by default)
+    fn contains(&self, (x, y): Coords) -> bool {
+        let (n, m) = self.size();
+        x < n && y < m
+    }
+
+    /// World position of the top left cell of a sudoku (in the diamond pattern of
+    /// [`sudoku_origin`] by default)
+    ///
+    /// Sudokus that are not drawn in the diamond pattern are found by their origin instead
+    /// of with [`Topology::sudoku_at`].
+    fn origin(&self, (x, y): Coords) -> (isize, isize) {
+        sudoku_origin(x as isize, y as isize)
+    }
+
+    /// Get the sudoku drawn at sudoku coordinates that can be outside of the grid (with
+    /// sudoku (x, y) drawn at [`sudoku_origin`]`(x, y)`), and whether it is drawn mirrored
+    /// there
+    ///
+    /// By default, nothing is drawn outside of the grid.
+    fn sudoku_at(&self, (x, y): (isize, isize)) -> Option<(Coords, bool)> {
+        let coords = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+        self.contains(coords).then_some((coords, false))
+    }
+
+    /// Get the blocks of other sudokus that block i of a sudoku is shared with
+    ///
+    /// This has to be symmetric: the other blocks are shared with block i of this sudoku
+    /// (and with each other). By default, corner blocks are shared with the sudokus drawn
+    /// next to them.
+    fn shared_with(&self, (x, y): Coords, i: usize) -> Vec<SharedBlock> {
+        let (x, y) = (x as isize, y as isize);
+        let boxes = self.box_size();
+        let (top_right, bottom_left) = (boxes.top_right_block(), boxes.bottom_left_block());
+        let (neighbour, block) = match i {
+            TOP_LEFT_BLOCK => ((x, y + 1), boxes.bottom_right_block()),
+            i if i == top_right => ((x + 1, y), bottom_left),
+            i if i == bottom_left => ((x - 1, y), top_right),
+            i if i == boxes.bottom_right_block() => ((x, y - 1), TOP_LEFT_BLOCK),
+            _ => return Vec::new(),
+        };
+        let Some((sudoku, mirrored)) = self.sudoku_at(neighbour) else {
+            return Vec::new();
+        };
+        vec![SharedBlock {
+            sudoku,
+            // blocks are mirrored in a sudoku like cells are in a block
+            block: if mirrored { boxes.mirror(block) } else { block },
+            mirrored,
+        }]
+    }
+
+    /// How the edges are connected, for the n by m worlds of [`GridTopology`]
+    fn wrap(&self) -> Option<Wrap> {
+        None
+    }
+
+    /// The preset this is, if any
+    ///
+    /// Only n by m worlds, presets, and edge tilings can be saved.
+    fn preset(&self) -> Option<Preset> {
+        None
+    }
+
+    /// What neighbours share, for the n by m worlds of [`EdgeTopology`]
+    fn edge_overlap(&self) -> Option<EdgeOverlap> {
+        None
+    }
+}
+
+/// How the edges of an n by m world are connected
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    /// Opposite edges are joined, so every sudoku shares all of its corner blocks
+    #[default]
+    Torus,
+    /// Sudokus on the edges have corner blocks of their own
+    Bounded,
+    /// The left and right edges are joined upside down, the top and bottom are not joined
+    ///
+    /// Sudokus across the join are mirrored, so the blocks they share are seen
+    /// mirrored as well.
+    Mobius,
+    /// The left and right edges are joined upside down, the top and bottom edges are joined
+    /// like in a torus
+    KleinBottle,
+}
+
+impl Wrap {
+    pub const ALL: [Wrap; 4] = [Wrap::Torus, Wrap::Bounded, Wrap::Mobius, Wrap::KleinBottle];
+
+    /// Name of the wrap in the text format
+    pub fn name(self) -> &'static str {
+        match self {
+            Wrap::Torus => "torus",
+            Wrap::Bounded => "bounded",
+            Wrap::Mobius => "mobius",
+            Wrap::KleinBottle => "klein",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Wrap> {
+        Wrap::ALL.into_iter().find(|w| w.name() == name)
+    }
+
+    fn wraps_x(self) -> bool {
+        self != Wrap::Bounded
+    }
+
+    fn wraps_y(self) -> bool {
+        matches!(self, Wrap::Torus | Wrap::KleinBottle)
+    }
+
+    /// Whether joining the left and right edges turns the world upside down
+    fn twisted(self) -> bool {
+        matches!(self, Wrap::Mobius | Wrap::KleinBottle)
+    }
+}
+
+/// World of n by m sudokus, each sharing its corner blocks with the four sudokus around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridTopology {
+    pub n: usize,
+    pub m: usize,
+    pub wrap: Wrap,
+    pub boxes: BoxSize,
+}
+
+impl GridTopology {
+    pub fn new(n: usize, m: usize, wrap: Wrap) -> Self {
+        GridTopology {
+            n,
+            m,
+            wrap,
+            boxes: BoxSize::CLASSIC,
+        }
+    }
+
+    /// Use sudokus with blocks of another size, if they are valid (see
+    /// [`BoxSize::is_valid`]) and can be used with the wrap: when the edges are joined
+    /// upside down, the blocks shared across the join are mirrored, so they have to be
+    /// square
+    pub fn with_boxes(mut self, boxes: BoxSize) -> Result<Self, UnsupportedBoxes> {
+        if !boxes.is_valid() || (boxes.width != boxes.height && self.wrap.twisted()) {
+            return Err(UnsupportedBoxes {
+                boxes,
+                wrap: self.wrap,
+            });
+        }
+        self.boxes = boxes;
+        Ok(self)
+    }
+}
+
+/// Block size that a world can not have (see [`GridTopology::with_boxes`])
+#[derive(Debug, PartialEq)]
+pub struct UnsupportedBoxes {
+    pub boxes: BoxSize,
+    pub wrap: Wrap,
+}
+
+impl std::fmt::Display for UnsupportedBoxes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let BoxSize { width, height } = self.boxes;
+        if self.boxes.is_valid() {
+            write!(
+                f,
+                "{width}x{height} blocks can not be shared across the twisted join of a {:?} world",
+                self.wrap
+            )
+        } else {
+            write!(f, "{width}x{height} blocks are not supported")
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedBoxes {}
+
+impl Topology for GridTopology {
+    fn size(&self) -> (usize, usize) {
+        (self.n, self.m)
+    }
+
+    fn box_size(&self) -> BoxSize {
+        self.boxes
+    }
+
+    /// The diamond pattern of [`sudoku_origin`], stretched to the size of the blocks
+    fn origin(&self, (x, y): Coords) -> (isize, isize) {
+        grid_origin(self.boxes, x as isize, y as isize)
+    }
+
+    fn sudoku_at(&self, (x, y): (isize, isize)) -> Option<(Coords, bool)> {
+        let (n, m) = (self.n as isize, self.m as isize);
+        let mirrored = self.wrap.twisted() && x.div_euclid(n) % 2 != 0;
+        let y = if mirrored { m - 1 - y } else { y };
+        let x = if self.wrap.wraps_x() {
+            x.rem_euclid(n)
+        } else {
+            x
+        };
+        let y = if self.wrap.wraps_y() {
+            y.rem_euclid(m)
+        } else {
+            y
+        };
+        let inside = (0..n).contains(&x) && (0..m).contains(&y);
+        inside.then_some(((x as usize, y as usize), mirrored))
+    }
+
+    fn wrap(&self) -> Option<Wrap> {
+        Some(self.wrap)
+    }
+}
+
+/// Get the blocks of other sudokus drawn at the same place as block i of a sudoku, with the
+/// top left block of every sudoku drawn at `block_origin` (in blocks)
+fn blocks_at(
+    topology: &impl Topology,
+    block_origin: impl Fn(Coords) -> (isize, isize),
+    coords: Coords,
+    i: usize,
+) -> Vec<SharedBlock> {
+    let (ox, oy) = block_origin(coords);
+    let (bx, by) = (ox + (i % 3) as isize, oy + (i / 3) as isize);
+    let (n, m) = topology.size();
+    (0..n * m)
+        .map(|s| (s % n, s / n))
+        .filter(|other| *other != coords && topology.contains(*other))
+        .filter_map(|other| {
+            let (px, py) = block_origin(other);
+            let (dx, dy) = (bx - px, by - py);
+            ((0..3).contains(&dx) && (0..3).contains(&dy)).then_some(SharedBlock {
+                sudoku: other,
+                block: (dx + 3 * dy) as usize,
+                mirrored: false,
+            })
+        })
+        .collect()
+}
+
+/// What neighbouring sudokus of an [`EdgeTopology`] share
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeOverlap {
+    /// A band of 3 blocks along the edge, so corner blocks are part of four sudokus
+    #[default]
+    Band,
+    /// Only the block in the middle of the edge
+    Block,
+}
+
+impl EdgeOverlap {
+    pub const ALL: [EdgeOverlap; 2] = [EdgeOverlap::Band, EdgeOverlap::Block];
+
+    /// Name of the overlap in the text format
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeOverlap::Band => "band",
+            EdgeOverlap::Block => "block",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EdgeOverlap> {
+        EdgeOverlap::ALL.into_iter().find(|o| o.name() == name)
+    }
+}
+
+/// Bounded world of n by m sudokus in rows and columns, each sharing its edges with the
+/// sudokus next to it (instead of its corners with the sudokus diagonally next to it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeTopology {
+    pub n: usize,
+    pub m: usize,
+    pub overlap: EdgeOverlap,
+}
+
+impl EdgeTopology {
+    pub fn new(n: usize, m: usize, overlap: EdgeOverlap) -> Self {
+        EdgeTopology { n, m, overlap }
+    }
+}
+
+impl Topology for EdgeTopology {
+    fn size(&self) -> (usize, usize) {
+        (self.n, self.m)
+    }
+
+    fn origin(&self, (x, y): Coords) -> (isize, isize) {
+        let (x, y) = (x as isize, y as isize);
+        match self.overlap {
+            EdgeOverlap::Band => (6 * x, 6 * y),
+            // a gap of one block, so the shared block is drawn with both sudokus
+            EdgeOverlap::Block => (12 * x, 12 * y),
+        }
+    }
+
+    fn sudoku_at(&self, _: (isize, isize)) -> Option<(Coords, bool)> {
+        None // not drawn in the diamond pattern
+    }
+
+    fn shared_with(&self, (x, y): Coords, i: usize) -> Vec<SharedBlock> {
+        if self.overlap == EdgeOverlap::Band {
+            let block_origin = |(x, y): Coords| (2 * x as isize, 2 * y as isize);
+            return blocks_at(self, block_origin, (x, y), i);
+        }
+        let (neighbour, block) = match i {
+            MIDDLE_LEFT_BLOCK => (x.checked_sub(1).map(|x| (x, y)), MIDDLE_RIGHT_BLOCK),
+            MIDDLE_RIGHT_BLOCK => (Some((x + 1, y)), MIDDLE_LEFT_BLOCK),
+            TOP_CENTER_BLOCK => (y.checked_sub(1).map(|y| (x, y)), BOTTOM_CENTER_BLOCK),
+            BOTTOM_CENTER_BLOCK => (Some((x, y + 1)), TOP_CENTER_BLOCK),
+            _ => return Vec::new(),
+        };
+        neighbour
+            .filter(|sudoku| self.contains(*sudoku))
+            .map(|sudoku| SharedBlock {
+                sudoku,
+                block,
+                mirrored: false,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn edge_overlap(&self) -> Option<EdgeOverlap> {
+        Some(self.overlap)
+    }
+}
+
+/// Well-known puzzles made of several overlapping sudokus (gattai)
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Two sudokus sharing a corner block
+    Twodoku,
+    /// A sudoku sharing each of its corner blocks with another sudoku
+    Samurai,
+    /// Four sudokus in a ring, each sharing a corner block with the next
+    Sohei,
+    /// Four sudokus in a square of 4 by 4 blocks, so the center blocks are part of all of them
+    Butterfly,
+    /// A sudoku sharing two blocks of every edge with another sudoku, turned like a pinwheel
+    Kazaguruma,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Twodoku,
+        Preset::Samurai,
+        Preset::Sohei,
+        Preset::Butterfly,
+        Preset::Kazaguruma,
+    ];
+
+    /// Name of the preset in the text format
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Twodoku => "twodoku",
+            Preset::Samurai => "samurai",
+            Preset::Sohei => "sohei",
+            Preset::Butterfly => "butterfly",
+            Preset::Kazaguruma => "kazaguruma",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// Whether the sudokus are placed in the diamond pattern of [`sudoku_origin`]
+    fn diamond(self) -> bool {
+        matches!(self, Preset::Twodoku | Preset::Samurai | Preset::Sohei)
+    }
+
+    /// Position of the top left block of a sudoku, in blocks with y pointing down
+    fn block_origin(self, (x, y): Coords) -> (isize, isize) {
+        let (x, y) = (x as isize, y as isize);
+        match self {
+            // sudoku_origin(x, y) in blocks, one block higher
+            Preset::Twodoku | Preset::Samurai | Preset::Sohei => (2 * (x - y), -2 * (x + y)),
+            Preset::Butterfly => (x, y),
+            Preset::Kazaguruma => match (x, y) {
+                (1, 0) => (1, 0),
+                (2, 1) => (4, 1),
+                (1, 2) => (3, 4),
+                (0, 1) => (0, 3),
+                _ => (2, 2),
+            },
+        }
+    }
+}
+
+impl Topology for Preset {
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Preset::Twodoku => (1, 2),
+            Preset::Sohei | Preset::Butterfly => (2, 2),
+            Preset::Samurai | Preset::Kazaguruma => (3, 3),
+        }
+    }
+
+    fn contains(&self, (x, y): Coords) -> bool {
+        let (n, m) = self.size();
+        let inside = x < n && y < m;
+        match self {
+            // the center sudoku and the four around it
+            Preset::Samurai | Preset::Kazaguruma => inside && (x == 1 || y == 1),
+            _ => inside,
+        }
+    }
+
+    fn origin(&self, coords: Coords) -> (isize, isize) {
+        if self.diamond() {
+            return sudoku_origin(coords.0 as isize, coords.1 as isize);
+        }
+        let (bx, by) = self.block_origin(coords);
+        (3 * bx, 3 * by)
+    }
+
+    fn sudoku_at(&self, (x, y): (isize, isize)) -> Option<(Coords, bool)> {
+        let coords = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+        (self.diamond() && self.contains(coords)).then_some((coords, false))
+    }
+
+    fn shared_with(&self, coords: Coords, i: usize) -> Vec<SharedBlock> {
+        blocks_at(self, |c| self.block_origin(c), coords, i)
+    }
+
+    fn preset(&self) -> Option<Preset> {
+        Some(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+    use crate::sudokus::{SudokuGrid, BOTTOM_RIGHT_BLOCK, MIDDLE_CENTER_BLOCK, TOP_RIGHT_BLOCK};
+
+    /// Two sudokus next to each other that share their center blocks
+    #[derive(Debug)]
+    struct SharedCenter;
+
+    impl Topology for SharedCenter {
+        fn size(&self) -> (usize, usize) {
+            (2, 1)
+        }
+
+        fn shared_with(&self, (x, y): Coords, i: usize) -> Vec<SharedBlock> {
+            let other = SharedBlock {
+                sudoku: (1 - x, y),
+                block: i,
+                mirrored: false,
+            };
+            (i == MIDDLE_CENTER_BLOCK)
+                .then_some(other)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    /// A bounded 2 by 2 world without the top right sudoku
+    #[derive(Debug)]
+    struct Corner;
+
+    impl Topology for Corner {
+        fn size(&self) -> (usize, usize) {
+            (2, 2)
+        }
+
+        fn contains(&self, coords: Coords) -> bool {
+            coords != (1, 1) && coords.0 < 2 && coords.1 < 2
+        }
+    }
+
+    fn assert_roundtrip(layout: &Layout) {
+        for i in 0..layout.len() {
+            for p in layout.positions(i) {
+                assert_eq!(layout.index(p.sudoku, p.row, p.column), i);
+            }
+        }
+    }
+
+    #[test]
+    fn custom_topologies() {
+        let layout = Layout::new(SharedCenter);
+        assert_eq!(layout.len(), (9 + 8) * 9);
+        assert_eq!(
+            layout.block_start((0, 0), MIDDLE_CENTER_BLOCK),
+            layout.block_start((1, 0), MIDDLE_CENTER_BLOCK)
+        );
+        assert_eq!(layout.shared_with((0, 0), TOP_LEFT_BLOCK), []);
+        assert_roundtrip(&layout);
+
+        let layout = Layout::new(Corner);
+        assert!(!layout.contains((1, 1)));
+        assert_eq!(
+            layout.sudoku_coords().collect::<Vec<_>>(),
+            [(0, 0), (1, 0), (0, 1)]
+        );
+        // two shared blocks, and the unshared corner blocks are stored last
+        assert_eq!(layout.len(), (3 * 9 - 2) * 9);
+        assert_eq!(layout.block_start((1, 0), TOP_LEFT_BLOCK), 3 * 7 * 9);
+        assert_eq!(layout.block_start((0, 1), TOP_LEFT_BLOCK), 3 * 7 * 9 + 9);
+        assert_roundtrip(&layout);
+
+        // the same solver works for every topology
+        for mut sg in [
+            SudokuGrid::with_topology(SharedCenter),
+            SudokuGrid::with_topology(Corner),
+        ] {
+            sg.cells = sg.solutions(1).pop().expect("empty worlds are solvable");
+            assert!(sg.is_solved_all());
+        }
+    }
+
+    #[test]
+    fn presets() {
+        let blocks = |preset| Layout::new(preset).len() / 9;
+        assert_eq!(blocks(Preset::Twodoku), 2 * 9 - 1);
+        assert_eq!(blocks(Preset::Samurai), 5 * 9 - 4);
+        assert_eq!(blocks(Preset::Sohei), 4 * 9 - 4);
+        assert_eq!(blocks(Preset::Butterfly), 4 * 4);
+        assert_eq!(blocks(Preset::Kazaguruma), 5 * 9 - 8);
+
+        // the center sudoku of a samurai shares its corners like in the world view
+        let layout = Layout::new(Preset::Samurai);
+        assert_eq!(layout.shared_with((1, 1), TOP_LEFT_BLOCK), [(1, 2)]);
+        assert_eq!(layout.shared_with((1, 1), TOP_RIGHT_BLOCK), [(2, 1)]);
+        assert!(!layout.contains((0, 0)));
+
+        // the middle blocks of a butterfly are part of all four sudokus
+        let layout = Layout::new(Preset::Butterfly);
+        assert_eq!(
+            layout.shared_with((0, 0), MIDDLE_CENTER_BLOCK),
+            [(1, 0), (0, 1), (1, 1)]
+        );
+        assert_eq!(layout.shared_with((0, 0), TOP_LEFT_BLOCK), []);
+
+        for preset in Preset::ALL {
+            assert_eq!(Preset::from_name(preset.name()), Some(preset));
+            let layout = Layout::new(preset);
+            assert_roundtrip(&layout);
+            // every cell is drawn where its sudokus are
+            for i in 0..layout.len() {
+                for (x, y) in layout.world_positions(i) {
+                    assert_eq!(layout.cell_at_world(x, y), Some(i), "{preset:?} cell {i}");
+                }
+            }
+
+            let mut sg = SudokuGrid::with_topology(preset);
+            sg.cells = sg.solutions(1).pop().expect("empty presets are solvable");
+            assert!(sg.is_solved_all(), "{preset:?}");
+        }
+    }
+
+    #[test]
+    fn edge_tilings() {
+        // a 5 by 5 square of blocks
+        let layout = Layout::new(EdgeTopology::new(2, 2, EdgeOverlap::Band));
+        assert_eq!(layout.len(), 5 * 5 * 9);
+        assert_eq!(
+            layout.shared_with((0, 0), BOTTOM_RIGHT_BLOCK),
+            [(1, 0), (0, 1), (1, 1)]
+        );
+        assert_eq!(layout.shared_with((0, 0), MIDDLE_RIGHT_BLOCK), [(1, 0)]);
+        assert_eq!(layout.shared_with((0, 0), TOP_LEFT_BLOCK), []);
+
+        // 3 sudokus in 2 rows share 4 horizontal and 3 vertical edges
+        let layout = Layout::new(EdgeTopology::new(3, 2, EdgeOverlap::Block));
+        assert_eq!(layout.len(), (6 * 9 - 4 - 3) * 9);
+        assert_eq!(layout.shared_with((0, 0), MIDDLE_RIGHT_BLOCK), [(1, 0)]);
+        assert_eq!(layout.shared_with((0, 0), MIDDLE_LEFT_BLOCK), []);
+        assert_eq!(layout.shared_with((1, 1), TOP_CENTER_BLOCK), [(1, 0)]);
+        assert_eq!(layout.shared_with((1, 1), BOTTOM_RIGHT_BLOCK), []);
+
+        for overlap in EdgeOverlap::ALL {
+            assert_eq!(EdgeOverlap::from_name(overlap.name()), Some(overlap));
+            let topology = EdgeTopology::new(3, 2, overlap);
+            let layout = Layout::new(topology);
+            assert_roundtrip(&layout);
+            for i in 0..layout.len() {
+                for (x, y) in layout.world_positions(i) {
+                    assert_eq!(layout.cell_at_world(x, y), Some(i), "{overlap:?} cell {i}");
+                }
+            }
+
+            let mut sg = SudokuGrid::with_topology(topology);
+            sg.cells = sg
+                .solutions(1)
+                .pop()
+                .expect("empty edge tilings are solvable");
+            assert!(sg.is_solved_all(), "{overlap:?}");
+        }
+    }
+
+    #[test]
+    fn shared_blocks_are_symmetric() {
+        for wrap in [Wrap::Torus, Wrap::Bounded, Wrap::Mobius, Wrap::KleinBottle] {
+            for (n, m) in [(1, 1), (3, 2), (2, 3)] {
+                let topology = GridTopology::new(n, m, wrap);
+                for (x, y, i) in
+                    (0..n).flat_map(|x| (0..m).flat_map(move |y| (0..9).map(move |i| (x, y, i))))
+                {
+                    for shared in topology.shared_with((x, y), i) {
+                        let back = topology.shared_with(shared.sudoku, shared.block);
+                        let this = SharedBlock {
+                            sudoku: (x, y),
+                            block: i,
+                            mirrored: shared.mirrored,
+                        };
+                        assert!(
+                            back.contains(&this),
+                            "{wrap:?} {n}x{m} ({x}, {y}) block {i}"
+                        );
+                    }
+                }
+            }
+        }
+
+        let others: Vec<Box<dyn Topology>> = Preset::ALL
+            .into_iter()
+            .map(|p| Box::new(p) as Box<dyn Topology>)
+            .chain(
+                EdgeOverlap::ALL
+                    .into_iter()
+                    .map(|overlap| Box::new(EdgeTopology::new(3, 3, overlap)) as Box<dyn Topology>),
+            )
+            .collect();
+        for topology in others {
+            let (n, m) = topology.size();
+            let coords = (0..n * m).map(|s| (s % n, s / n));
+            for coords in coords.filter(|c| topology.contains(*c)) {
+                for i in 0..9 {
+                    for shared in topology.shared_with(coords, i) {
+                        let back = topology.shared_with(shared.sudoku, shared.block);
+                        assert!(
+                            back.iter().any(|b| (b.sudoku, b.block) == (coords, i)),
+                            "{topology:?} {coords:?} block {i}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boxes_for_wraps() {
+        let wide = BoxSize::new(3, 2).unwrap();
+        for wrap in [Wrap::Torus, Wrap::Bounded] {
+            let topology = GridTopology::new(2, 2, wrap).with_boxes(wide);
+            assert_eq!(topology.map(|t| t.boxes), Ok(wide));
+        }
+        for wrap in [Wrap::Mobius, Wrap::KleinBottle] {
+            let topology = GridTopology::new(2, 2, wrap);
+            assert_eq!(
+                topology.with_boxes(wide),
+                Err(UnsupportedBoxes { boxes: wide, wrap })
+            );
+            assert!(topology.with_boxes(BoxSize::new(2, 2).unwrap()).is_ok());
+        }
+        let too_large = BoxSize {
+            width: 8,
+            height: 8,
+        };
+        assert!(GridTopology::new(1, 1, Wrap::Torus)
+            .with_boxes(too_large)
+            .is_err());
+    }
+}